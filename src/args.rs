@@ -1,13 +1,106 @@
-use std::path::PathBuf;
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
+use crate::{Prose, TypoCategory, report::Outcome};
+
 #[derive(Clone, Debug, ValueEnum)]
 pub enum OutputFormat {
     /// JSON output
     Json,
     /// Text output
     Text,
+    /// One line per typo, as `path:line:column: unknown word 'foo'`
+    Short,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum UnusedWords {
+    /// Report allowlist words that were never needed
+    Warn,
+    /// Also fail the check if there are any
+    Error,
+}
+
+#[derive(Clone, Debug, ValueEnum)]
+pub enum GroupBy {
+    /// List each misspelled word once, with all of its locations
+    Word,
+}
+
+/// The exit code of `check` for each [`Outcome`], parsed from
+/// `typos=2,error=1,clean=0`. Outcomes that aren't listed keep their default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExitCodeMap {
+    pub clean: i32,
+    pub typos: i32,
+    pub error: i32,
+}
+
+impl Default for ExitCodeMap {
+    fn default() -> Self {
+        Self {
+            clean: 0,
+            typos: 1,
+            error: 1,
+        }
+    }
+}
+
+impl ExitCodeMap {
+    pub fn code(&self, outcome: Outcome) -> i32 {
+        match outcome {
+            Outcome::Clean => self.clean,
+            Outcome::Typos => self.typos,
+            Outcome::Error => self.error,
+        }
+    }
+}
+
+impl FromStr for ExitCodeMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::default();
+        for entry in s.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let Some((outcome, code)) = entry.split_once('=') else {
+                return Err(format!("expected `outcome=code`, found `{entry}`"));
+            };
+            let code = code
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid exit code: `{}`", code.trim()))?;
+            match outcome.trim() {
+                "clean" => map.clean = code,
+                "typos" => map.typos = code,
+                "error" => map.error = code,
+                other => {
+                    return Err(format!(
+                        "unknown outcome `{other}`, expected `clean`, `typos`, or `error`"
+                    ));
+                }
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Parse a suggestion threshold, which must be between 0 and 1.
+fn parse_suggestion_threshold(s: &str) -> Result<f64, String> {
+    let threshold: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid threshold: `{}`", s.trim()))?;
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(format!(
+            "threshold must be between 0 and 1, found {threshold}"
+        ));
+    }
+    Ok(threshold)
 }
 
 #[expect(dead_code)]
@@ -20,20 +113,40 @@ pub trait ContextArgs {
     fn follow_symlinks(&self) -> bool;
     fn max_filesize(&self) -> Option<u64>;
     fn jobs(&self) -> Option<usize>;
+    fn dictionary_load_concurrency(&self) -> Option<NonZeroUsize>;
     fn settings(&self) -> Option<PathBuf>;
     fn output(&self) -> Option<OutputFormat>;
+    fn words(&self) -> Vec<String>;
+    fn ignore_words_lists(&self) -> Vec<PathBuf>;
+    fn dictionaries_from_config_only(&self) -> bool;
+    fn dictionary_dir(&self) -> Option<PathBuf>;
+    fn only_dictionaries(&self) -> Vec<String>;
+    fn disabled_dictionaries(&self) -> Vec<String>;
+    fn leetspeak(&self) -> bool;
+    fn strip_prefixes(&self) -> Vec<String>;
+    fn flatten_dictionaries(&self) -> bool;
+    fn min_word_frequency(&self) -> Option<u64>;
+    fn suggestion_threshold(&self) -> Option<f64>;
+    fn prose_only(&self) -> Option<Prose>;
 }
 
 #[derive(Clone, Debug, Args)]
 pub struct CheckArgs {
-    /// The path to the folder to search
-    pub dir: PathBuf,
+    /// Files to check, or folders to search for files to check
+    #[clap(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Only check the files found in folders that match this glob, instead
+    /// of `globs` from the settings [default: **/*.*]
+    #[clap(long)]
     pub glob: Option<String>,
     /// Verbose output
     #[clap(short, long, default_value_t = false)]
     pub verbose: bool,
     #[clap(short, long, default_value_t = false)]
     pub progress: bool,
+    /// Only print typos and errors
+    #[clap(short, long, default_value_t = false, conflicts_with_all = ["verbose", "progress"])]
+    pub quiet: bool,
     /// Which files/folders to exclude from the search
     #[clap(long)]
     pub exclude: Vec<String>,
@@ -47,15 +160,126 @@ pub struct CheckArgs {
     pub max_filesize: Option<u64>,
     #[clap(short, long)]
     pub jobs: Option<usize>,
+    /// Compile at most this many dictionaries at once, to bound peak memory
+    #[clap(long)]
+    pub dictionary_load_concurrency: Option<NonZeroUsize>,
     #[clap(long)]
     pub settings: Option<PathBuf>,
     #[clap(long)]
     pub output: Option<OutputFormat>,
+    /// Additional words to allow
+    #[clap(long = "word")]
+    pub words: Vec<String>,
+    /// Files containing additional words to allow, one per line
+    #[clap(long = "ignore-words-list")]
+    pub ignore_words_lists: Vec<PathBuf>,
+    /// Only use dictionaries listed in the settings, instead of every
+    /// dictionary in the store
+    #[clap(long, default_value_t = false)]
+    pub dictionaries_from_config_only: bool,
+    /// A folder of dictionaries vendored in the project, used in addition
+    /// to the store. Overrides `dictionary_dir` in the settings
+    #[clap(long)]
+    pub dictionary_dir: Option<PathBuf>,
+    /// Only use these dictionaries, instead of those enabled in the settings
+    #[clap(long = "only-dict")]
+    pub only_dictionaries: Vec<String>,
+    /// Don't use these dictionaries, even if enabled in the settings
+    #[clap(long = "disable-dict")]
+    pub disabled_dictionaries: Vec<String>,
+    /// Report obfuscated words like `passw0rd` in comments and strings
+    #[clap(long, default_value_t = false)]
+    pub leetspeak: bool,
+    /// Prefixes of generated identifiers to strip before checking
+    #[clap(long, value_delimiter = ',')]
+    pub strip_prefixes: Vec<String>,
+    /// Merge the enabled dictionaries into one before checking, trading
+    /// startup time for faster lookups
+    #[clap(long, default_value_t = false)]
+    pub flatten_dictionaries: bool,
+    /// Treat dictionary words used less often than this as unknown, for
+    /// dictionaries that list word frequencies
+    #[clap(long)]
+    pub min_word_frequency: Option<u64>,
+    /// Only suggest words more similar to a typo than this, from 0 to 1.
+    /// Lower thresholds give more, but noisier, suggestions [default: 0.7]
+    #[clap(long, value_parser = parse_suggestion_threshold)]
+    pub suggestion_threshold: Option<f64>,
+    /// Report project words and custom dictionary words that no checked word
+    /// needed
+    #[clap(long)]
+    pub unused_words: Option<UnusedWords>,
+    /// Print how long loading dictionaries, finding files, and checking them
+    /// took
+    #[clap(long, default_value_t = false)]
+    pub time_report: bool,
+    /// Check every file instead of reusing the results of unchanged files
+    #[clap(long, default_value_t = false)]
+    pub no_result_cache: bool,
+    /// Only check comments, in files with a grammar
+    #[clap(long, default_value_t = false, conflicts_with = "strings_only")]
+    pub comments_only: bool,
+    /// Only check strings, in files with a grammar
+    #[clap(long, default_value_t = false)]
+    pub strings_only: bool,
+    /// Group the reported typos instead of printing each one
+    #[clap(long)]
+    pub group_by: Option<GroupBy>,
+    /// Show paths relative to this directory instead of as found
+    #[clap(long)]
+    pub relative_to: Option<PathBuf>,
+    /// Don't respect `.ignore` or VCS ignore files
+    #[clap(long, default_value_t = false)]
+    pub no_ignore: bool,
+    /// Don't respect `.gitignore`, the global gitignore, or `.git/info/exclude`
+    #[clap(long, default_value_t = false)]
+    pub no_ignore_vcs: bool,
+    /// Also check hidden files and directories
+    #[clap(long, default_value_t = false)]
+    pub hidden: bool,
+    /// Read a unified diff from stdin and only check the lines it adds
+    #[clap(long, default_value_t = false)]
+    pub diff_stdin: bool,
+    /// Also write the results to a JSON Lines file, for `merge-results`
+    #[clap(long)]
+    pub export_results: Option<PathBuf>,
+    /// Only check the files staged in git, instead of walking the folder
+    #[clap(long, default_value_t = false, conflicts_with = "diff_stdin")]
+    pub staged: bool,
+    /// Only check the files changed in git since this reference, like
+    /// `origin/main`, instead of walking the folder
+    #[clap(long, conflicts_with_all = ["diff_stdin", "staged"])]
+    pub since: Option<String>,
+    /// Exit codes for each outcome, like `typos=2,error=1,clean=0`
+    #[clap(long, default_value = "clean=0,typos=1,error=1")]
+    pub exit_code_map: ExitCodeMap,
+    /// Only count typos of these categories as typos for the exit code
+    /// [default: unknown,forbidden]
+    #[clap(long, value_delimiter = ',')]
+    pub error_on: Vec<TypoCategory>,
+    /// Stop at the first typo found, reporting only that typo
+    #[clap(long, default_value_t = false, conflicts_with = "watch")]
+    pub fail_fast: bool,
+    /// Print how many files were checked and why the others were skipped
+    #[clap(long, default_value_t = false)]
+    pub report_coverage: bool,
+    /// Keep running and re-check files as they change
+    #[clap(long, default_value_t = false, conflicts_with_all = ["diff_stdin", "export_results"])]
+    pub watch: bool,
+    /// With `--watch`, only report re-checked files whose typos changed
+    /// since they were last reported
+    #[clap(long, default_value_t = false, requires = "watch")]
+    pub quiet_unchanged: bool,
 }
 
 impl ContextArgs for CheckArgs {
+    /// The folder searched, or the working directory when checking files or
+    /// several folders.
     fn dir(&self) -> PathBuf {
-        self.dir.clone()
+        match self.paths.as_slice() {
+            [path] if path.is_dir() => path.clone(),
+            _ => PathBuf::from("."),
+        }
     }
 
     fn verbose(&self) -> bool {
@@ -86,6 +310,10 @@ impl ContextArgs for CheckArgs {
         self.jobs
     }
 
+    fn dictionary_load_concurrency(&self) -> Option<NonZeroUsize> {
+        self.dictionary_load_concurrency
+    }
+
     fn settings(&self) -> Option<PathBuf> {
         self.settings.clone()
     }
@@ -93,6 +321,77 @@ impl ContextArgs for CheckArgs {
     fn output(&self) -> Option<OutputFormat> {
         self.output.clone()
     }
+
+    fn words(&self) -> Vec<String> {
+        self.words.clone()
+    }
+
+    fn ignore_words_lists(&self) -> Vec<PathBuf> {
+        self.ignore_words_lists.clone()
+    }
+
+    fn dictionaries_from_config_only(&self) -> bool {
+        self.dictionaries_from_config_only
+    }
+
+    fn dictionary_dir(&self) -> Option<PathBuf> {
+        self.dictionary_dir.clone()
+    }
+
+    fn only_dictionaries(&self) -> Vec<String> {
+        self.only_dictionaries.clone()
+    }
+
+    fn disabled_dictionaries(&self) -> Vec<String> {
+        self.disabled_dictionaries.clone()
+    }
+
+    fn leetspeak(&self) -> bool {
+        self.leetspeak
+    }
+
+    fn strip_prefixes(&self) -> Vec<String> {
+        self.strip_prefixes.clone()
+    }
+
+    fn flatten_dictionaries(&self) -> bool {
+        self.flatten_dictionaries
+    }
+
+    fn min_word_frequency(&self) -> Option<u64> {
+        self.min_word_frequency
+    }
+
+    fn suggestion_threshold(&self) -> Option<f64> {
+        self.suggestion_threshold
+    }
+
+    fn prose_only(&self) -> Option<Prose> {
+        if self.comments_only {
+            Some(Prose::Comments)
+        } else if self.strings_only {
+            Some(Prose::Strings)
+        } else {
+            None
+        }
+    }
+}
+
+impl CheckArgs {
+    /// Use a last path that doesn't exist but looks like a glob as `--glob`,
+    /// for the old `check <DIR> <GLOB>` form.
+    pub fn split_glob(&mut self) {
+        if self.glob.is_some() || self.paths.len() < 2 {
+            return;
+        }
+        let Some(last) = self.paths.last().and_then(|path| path.to_str()) else {
+            return;
+        };
+        if !Path::new(last).exists() && last.contains(['*', '?', '[']) {
+            self.glob = Some(last.to_string());
+            self.paths.pop();
+        }
+    }
 }
 
 #[derive(Clone, Debug, Args)]
@@ -143,6 +442,10 @@ impl ContextArgs for TraceArgs {
         None
     }
 
+    fn dictionary_load_concurrency(&self) -> Option<NonZeroUsize> {
+        None
+    }
+
     fn settings(&self) -> Option<PathBuf> {
         self.settings.clone()
     }
@@ -150,6 +453,301 @@ impl ContextArgs for TraceArgs {
     fn output(&self) -> Option<OutputFormat> {
         self.output.clone()
     }
+
+    fn words(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn ignore_words_lists(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+
+    fn dictionaries_from_config_only(&self) -> bool {
+        false
+    }
+
+    fn dictionary_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn only_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn disabled_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn leetspeak(&self) -> bool {
+        false
+    }
+
+    fn strip_prefixes(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn flatten_dictionaries(&self) -> bool {
+        false
+    }
+
+    fn min_word_frequency(&self) -> Option<u64> {
+        None
+    }
+
+    fn suggestion_threshold(&self) -> Option<f64> {
+        None
+    }
+
+    fn prose_only(&self) -> Option<Prose> {
+        None
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct LspArgs {
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+}
+
+impl ContextArgs for LspArgs {
+    fn dir(&self) -> PathBuf {
+        PathBuf::from(".")
+    }
+
+    fn verbose(&self) -> bool {
+        false
+    }
+
+    fn extra_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn exclude(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn max_depth(&self) -> Option<usize> {
+        None
+    }
+
+    fn follow_symlinks(&self) -> bool {
+        true
+    }
+
+    fn max_filesize(&self) -> Option<u64> {
+        None
+    }
+
+    fn jobs(&self) -> Option<usize> {
+        None
+    }
+
+    fn dictionary_load_concurrency(&self) -> Option<NonZeroUsize> {
+        None
+    }
+
+    fn settings(&self) -> Option<PathBuf> {
+        self.settings.clone()
+    }
+
+    fn output(&self) -> Option<OutputFormat> {
+        None
+    }
+
+    fn words(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn ignore_words_lists(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+
+    fn dictionaries_from_config_only(&self) -> bool {
+        false
+    }
+
+    fn dictionary_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn only_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn disabled_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn leetspeak(&self) -> bool {
+        false
+    }
+
+    fn strip_prefixes(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn flatten_dictionaries(&self) -> bool {
+        false
+    }
+
+    fn min_word_frequency(&self) -> Option<u64> {
+        None
+    }
+
+    fn suggestion_threshold(&self) -> Option<f64> {
+        None
+    }
+
+    fn prose_only(&self) -> Option<Prose> {
+        None
+    }
+}
+
+/// Arguments of a check started through the library, which only uses the
+/// settings it's given.
+#[derive(Clone, Debug)]
+pub struct LibraryArgs {
+    /// The folder the project words file is found in
+    pub dir: PathBuf,
+}
+
+impl ContextArgs for LibraryArgs {
+    fn dir(&self) -> PathBuf {
+        self.dir.clone()
+    }
+
+    fn verbose(&self) -> bool {
+        false
+    }
+
+    fn extra_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn exclude(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn max_depth(&self) -> Option<usize> {
+        None
+    }
+
+    fn follow_symlinks(&self) -> bool {
+        true
+    }
+
+    fn max_filesize(&self) -> Option<u64> {
+        None
+    }
+
+    fn jobs(&self) -> Option<usize> {
+        None
+    }
+
+    fn dictionary_load_concurrency(&self) -> Option<NonZeroUsize> {
+        None
+    }
+
+    /// Where the folder's settings would be, so its project words file is
+    /// found there rather than in the working directory
+    fn settings(&self) -> Option<PathBuf> {
+        Some(self.dir.join("code-spellcheck.json"))
+    }
+
+    fn output(&self) -> Option<OutputFormat> {
+        None
+    }
+
+    fn words(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn ignore_words_lists(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+
+    fn dictionaries_from_config_only(&self) -> bool {
+        false
+    }
+
+    fn dictionary_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn only_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn disabled_dictionaries(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn leetspeak(&self) -> bool {
+        false
+    }
+
+    fn strip_prefixes(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn flatten_dictionaries(&self) -> bool {
+        false
+    }
+
+    fn min_word_frequency(&self) -> Option<u64> {
+        None
+    }
+
+    fn suggestion_threshold(&self) -> Option<f64> {
+        None
+    }
+
+    fn prose_only(&self) -> Option<Prose> {
+        None
+    }
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct ExportArgs {
+    /// The name of the dictionary to export
+    pub name: String,
+    /// Write the words to a file instead of stdout
+    #[clap(short = 'o', long = "out")]
+    pub out: Option<PathBuf>,
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+    #[clap(long)]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct CheckDictArgs {
+    /// The name of, or path to, the dictionary to compile
+    pub name: String,
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+    #[clap(long)]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct StatsArgs {
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+    #[clap(long)]
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct MergeResultsArgs {
+    /// Results files written by `check --export-results`
+    #[clap(required = true)]
+    pub files: Vec<PathBuf>,
+    #[clap(short, long, default_value_t = false)]
+    pub progress: bool,
+    /// Group the reported typos instead of printing each one
+    #[clap(long)]
+    pub group_by: Option<GroupBy>,
 }
 
 #[derive(Clone, Debug, Args)]
@@ -159,26 +757,188 @@ pub struct InstallArgs {
     pub yes: bool,
 }
 
+#[derive(Clone, Debug, Args)]
+pub struct ImportCspellArgs {
+    /// Git repository to import the cspell dictionaries from, e.g. a mirror
+    #[arg(long, env = "CSC_CSPELL_REPO", default_value = crate::cspell::DEFAULT_REPO)]
+    pub cspell_repo: String,
+    /// Trim word lists and remove empty lines and duplicates, lowercasing
+    /// words for case-insensitive dictionaries
+    #[arg(long, default_value_t = false)]
+    pub normalize: bool,
+    /// Commits of history to clone the repository with, or 0 for all of them
+    #[arg(long, default_value_t = 1)]
+    pub depth: u32,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct ImportCspellConfigArgs {
+    /// The cspell configuration to import, e.g. `cspell.json`
+    pub path: PathBuf,
+    /// The settings file to write to, defaulting to `code-spellcheck.json`.
+    /// Imported entries are added to its existing settings
+    #[arg(short = 'o', long = "out")]
+    pub out: Option<PathBuf>,
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum CacheCommand {
-    /// Compile the wordlists
-    Build,
+    /// Compile the wordlists and remove cache entries whose wordlist is gone
+    Build {
+        /// Only remove orphaned cache entries, without compiling
+        #[arg(long, default_value_t = false)]
+        prune_only: bool,
+    },
     /// Clear the cache
     Clear,
     /// List all cache items
     List,
 }
 
+#[derive(Clone, Debug, Args)]
+pub struct DictionaryDiffArgs {
+    /// The old wordlist, or with `--compiled` the name of, or path to, the
+    /// old dictionary
+    pub old: String,
+    /// The new wordlist, or with `--compiled` the name of, or path to, the
+    /// new dictionary
+    pub new: String,
+    /// Compile both dictionaries and compare their words, rather than the
+    /// entries of two wordlists
+    #[clap(long, default_value_t = false)]
+    pub compiled: bool,
+    /// Only print entries that were added
+    #[clap(long, default_value_t = false, conflicts_with = "removed_only")]
+    pub added_only: bool,
+    /// Only print entries that were removed
+    #[clap(long, default_value_t = false)]
+    pub removed_only: bool,
+    /// Settings to find dictionaries by name in, with `--compiled`
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum DictionaryCommand {
+    /// Compare the entries of two wordlists, or the words of two compiled
+    /// dictionaries
+    Diff(DictionaryDiffArgs),
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct ValidateArgs {
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct ConfigArgs {
+    #[clap(long)]
+    pub settings: Option<PathBuf>,
+    /// Write a settings file with the defaults instead, if there's none
+    #[clap(long, default_value_t = false)]
+    pub init: bool,
+}
+
+#[derive(Clone, Debug, Args)]
+pub struct CompletionsArgs {
+    /// The shell to generate completions for
+    pub shell: clap_complete::Shell,
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: CliArgs,
+    /// Look for `code-spellcheck.json` at the root of the enclosing git
+    /// repository instead of the working directory
+    #[arg(long, global = true, default_value_t = false)]
+    pub config_from_git_root: bool,
+    /// Use cloned git dictionaries as they are instead of fetching updates
+    #[arg(long, global = true, default_value_t = false)]
+    pub offline: bool,
+    /// Don't read or write compiled dictionaries and file results in the
+    /// cache, e.g. on ephemeral CI runners
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_cache: bool,
+    /// Directory for installed dictionaries and caches, instead of
+    /// `~/.code-spellcheck`
+    #[arg(long, global = true, env = "CSC_HOME")]
+    pub home: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
 pub enum CliArgs {
     /// Check for typos
-    Check(CheckArgs),
+    Check(Box<CheckArgs>),
     #[command(subcommand)]
     Cache(CacheCommand),
     Trace(TraceArgs),
-    Lsp,
+    /// Run a language server over stdio
+    Lsp(LspArgs),
     Install(InstallArgs),
     /// Import cspell dictionaries
-    ImportCspell,
+    ImportCspell(ImportCspellArgs),
+    /// Import the words, dictionaries, and ignored paths of a cspell
+    /// configuration into the settings
+    ImportCspellConfig(ImportCspellConfigArgs),
+    /// Inspect wordlists
+    #[command(subcommand)]
+    Dictionary(DictionaryCommand),
+    /// Print the words of a compiled dictionary
+    Export(ExportArgs),
+    /// Compile a dictionary and report its word count, without checking
+    /// any files
+    CheckDict(CheckDictArgs),
+    /// Count the words of each enabled dictionary, and the words no other
+    /// dictionary has
+    Stats(StatsArgs),
+    /// Check the settings for unknown keys and missing dictionaries
+    Validate(ValidateArgs),
+    /// Print the settings in effect, with the defaults filled in
+    Config(ConfigArgs),
+    /// Combine the results of several `check --export-results` runs
+    MergeResults(MergeResultsArgs),
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+    /// Print the man page in roff format
+    #[command(hide = true)]
+    Man,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exit_code_map() {
+        let map = "typos=2, error=3".parse::<ExitCodeMap>().unwrap();
+        assert_eq!(map.code(Outcome::Clean), 0);
+        assert_eq!(map.code(Outcome::Typos), 2);
+        assert_eq!(map.code(Outcome::Error), 3);
+        assert!("typos".parse::<ExitCodeMap>().is_err());
+        assert!("warnings=2".parse::<ExitCodeMap>().is_err());
+        assert!("typos=two".parse::<ExitCodeMap>().is_err());
+    }
+
+    #[test]
+    fn test_split_glob() {
+        let mut args = Cli::parse_from(["cargo-csc", "check", "src", "**/*.rs"]);
+        let CliArgs::Check(ref mut check) = args.command else {
+            panic!("expected check");
+        };
+        check.split_glob();
+        assert_eq!(check.paths, vec![PathBuf::from("src")]);
+        assert_eq!(check.glob.as_deref(), Some("**/*.rs"));
+    }
+
+    #[test]
+    fn test_parse_suggestion_threshold() {
+        assert_eq!(parse_suggestion_threshold("0.5"), Ok(0.5));
+        assert_eq!(parse_suggestion_threshold("1"), Ok(1.0));
+        assert!(parse_suggestion_threshold("1.5").is_err());
+        assert!(parse_suggestion_threshold("-0.1").is_err());
+        assert!(parse_suggestion_threshold("NaN").is_err());
+    }
 }
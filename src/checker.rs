@@ -0,0 +1,39 @@
+use crate::{MultiTrie, Typo, check_text};
+
+/// Checks strings against a set of compiled dictionaries, without a runtime,
+/// settings, or file IO.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use cargo_csc::{Checker, Dictionary, MultiTrie};
+///
+/// let dictionary = Dictionary::new_from_strings(&["hello".to_string(), "world".to_string()]);
+/// let mut words = MultiTrie::new();
+/// words.inner.push(Arc::new(dictionary.compile().unwrap()));
+/// let checker = Checker::new(words);
+///
+/// let typos = checker.check_str("// hello wrold", Some("rust"));
+/// assert_eq!(typos[0].word, "wrold");
+/// assert!(checker.check_str("hello world", None).is_empty());
+/// ```
+pub struct Checker {
+    words: MultiTrie,
+}
+
+impl Checker {
+    pub fn new(words: MultiTrie) -> Self {
+        Self { words }
+    }
+
+    /// The dictionaries words are looked up in.
+    pub fn words(&self) -> &MultiTrie {
+        &self.words
+    }
+
+    /// Check `text` for typos, parsed with the grammar of `language` (e.g.
+    /// `"rust"`) if there is one, otherwise word by word.
+    pub fn check_str(&self, text: &str, language: Option<&str>) -> Vec<Typo> {
+        check_text(text, language, &self.words)
+    }
+}
@@ -0,0 +1,1144 @@
+//! The `cargo-csc` command line interface, built on the checker in the
+//! crate root.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::Ordering},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use clap::{CommandFactory, Parser};
+use inquire::Confirm;
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::{io::AsyncReadExt, sync::Mutex, task, time::Instant};
+use url::Url;
+
+#[cfg(feature = "lsp")]
+use crate::lsp;
+use crate::{
+    CheckFileResult, Dictionary, FileToCheck, HashMap, HashSet, MergedSettings, Settings,
+    SharedRuntimeContext, args,
+    args::{
+        CacheCommand, CheckArgs, CheckDictArgs, Cli, CliArgs, ConfigArgs, ContextArgs,
+        DictionaryCommand, ExportArgs, MergeResultsArgs, OutputFormat, StatsArgs, TraceArgs,
+        UnusedWords, ValidateArgs,
+    },
+    cache_path, check_file, cspell, detect_language, dictionary,
+    dictionary::{
+        DictCacheStore, Rule, RuleChange, dict_cache_store_location, diff_rules,
+        load_dictionary_format_from_file,
+    },
+    filesystem, find_dictionary, git, handle_file, load_dictionaries, load_dictionary, matches_any,
+    report::{Coverage, Outcome, Reporter, TimeReport, load_results},
+    result_cache::ResultCache,
+    settings,
+    settings::DictionaryName,
+    store_path, unified_diff,
+};
+
+/// How long `check --watch` waits for more changes before re-checking.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The files given explicitly and those found in the given folders that
+/// match the globs.
+fn find_files(
+    args: &CheckArgs,
+    settings: &Settings,
+) -> anyhow::Result<impl Iterator<Item = PathBuf>> {
+    let patterns = settings
+        .glob_patterns(args.glob.as_deref())
+        .context("Failed to find the files to check")?;
+    let found = walk_files(args).filter(move |path| matches_any(&patterns, path));
+    Ok(explicit_files(args).chain(found))
+}
+
+/// The files given explicitly, which are checked even if they don't match
+/// the glob or are ignored.
+fn explicit_files(args: &CheckArgs) -> impl Iterator<Item = PathBuf> {
+    args.paths
+        .iter()
+        .filter(|path| path.is_file())
+        .cloned()
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Walk the given folders for files, respecting ignore files unless
+/// disabled.
+fn walk_files(args: &CheckArgs) -> impl Iterator<Item = PathBuf> {
+    let vcs_ignore = !args.no_ignore && !args.no_ignore_vcs;
+    let (ignore, hidden) = (!args.no_ignore, !args.hidden);
+    let mut dirs = args.paths.iter().filter(|path| path.is_dir());
+    let walk = dirs.next().map(|first| {
+        let mut builder = ignore::WalkBuilder::new(first);
+        for dir in dirs {
+            builder.add(dir);
+        }
+        builder
+            .ignore(ignore)
+            .parents(ignore)
+            .git_ignore(vcs_ignore)
+            .git_global(vcs_ignore)
+            .git_exclude(vcs_ignore)
+            .hidden(hidden)
+            .build()
+    });
+    walk.into_iter()
+        .flatten()
+        .flatten()
+        .map(ignore::DirEntry::into_path)
+        .filter(|path| path.is_file())
+}
+
+/// The files under the checked folder that are staged or, with `--since`,
+/// changed since a git reference, as paths from the checked folder.
+fn find_git_files(args: &CheckArgs) -> anyhow::Result<Vec<PathBuf>> {
+    let root = args.dir();
+    let dir = root
+        .canonicalize()
+        .context(format!("Failed to resolve path: {}", root.display()))?;
+    let files = match args.since {
+        Some(ref reference) => git::changed_since(&dir, reference)?,
+        None => git::staged_files(&dir)?,
+    };
+    Ok(files
+        .into_iter()
+        .filter_map(|path| Some(root.join(path.strip_prefix(&dir).ok()?)))
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+/// Create the reporter for the results of `check`.
+fn check_reporter(
+    args: &CheckArgs,
+    total_files: usize,
+    progress: bool,
+) -> anyhow::Result<Reporter> {
+    let mut reporter = Reporter::new(total_files, progress, args.group_by.clone());
+    if let Some(ref base) = args.relative_to {
+        reporter.relative_to(base)?;
+    }
+    if !args.error_on.is_empty() {
+        reporter.error_on(&args.error_on);
+    }
+    if matches!(args.output, Some(OutputFormat::Short)) {
+        reporter.short();
+    }
+    Ok(reporter)
+}
+
+/// The paths separated by commas, for messages.
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// What was reported for a file, to tell whether checking it again changed
+/// anything.
+fn reported(result: &CheckFileResult) -> Result<Vec<String>, String> {
+    let file = result.file.display().to_string();
+    match result.typos {
+        Ok(ref typos) => Ok(typos.iter().map(|typo| typo.to_short(&file)).collect()),
+        Err(ref e) => Err(format!("{e:#}")),
+    }
+}
+
+/// Re-check files as they change until interrupted, reusing the dictionaries
+/// loaded for the initial check. With `--quiet-unchanged`, files are only
+/// reported when what's reported for them differs from `last_reported`,
+/// which starts with the initial check.
+async fn watch(
+    context: Arc<SharedRuntimeContext>,
+    args: &CheckArgs,
+    mut last_reported: HashMap<PathBuf, Result<Vec<String>, String>>,
+) -> anyhow::Result<()> {
+    let (event_sender, mut event_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        // The receiver only goes away when watching stops
+        let _ = event_sender.send(event);
+    })
+    .context("Failed to start the file watcher")?;
+    for path in &args.paths {
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .context(format!("Failed to watch: {}", path.display()))?;
+    }
+    // Events have canonical paths, so map them back to the paths found
+    let find = || -> anyhow::Result<HashMap<_, _>> {
+        Ok(find_files(args, &context.settings.settings)?
+            .filter_map(|path| Some((path.canonicalize().ok()?, path)))
+            .collect())
+    };
+    let mut files = find()?;
+    if !args.quiet {
+        println!("Watching {} for changes", display_paths(&args.paths));
+    }
+    while let Some(event) = event_receiver.recv().await {
+        let mut events = vec![event];
+        // Saving a file often takes several events, so handle them together
+        tokio::time::sleep(WATCH_DEBOUNCE).await;
+        while let Ok(event) = event_receiver.try_recv() {
+            events.push(event);
+        }
+        let mut changed = BTreeSet::new();
+        for event in events {
+            let event: notify::Event = event.context("Failed to watch for changes")?;
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            if event.paths.iter().any(|path| !files.contains_key(path)) {
+                // A new file, which may be ignored
+                files = find()?;
+            }
+            changed.extend(
+                event
+                    .paths
+                    .iter()
+                    .filter_map(|path| files.get(path).cloned()),
+            );
+        }
+        if changed.is_empty() {
+            continue;
+        }
+        let mut results = Vec::new();
+        for file in changed {
+            let typos = check_file(&context, &file, None).await;
+            let result = CheckFileResult { file, typos };
+            if args.quiet_unchanged {
+                let reported = reported(&result);
+                if last_reported.get(&result.file) == Some(&reported) {
+                    continue;
+                }
+                last_reported.insert(result.file.clone(), reported);
+            }
+            results.push(result);
+        }
+        if results.is_empty() {
+            continue;
+        }
+        let mut reporter = check_reporter(args, results.len(), !args.quiet)?;
+        for result in &results {
+            reporter.report(result)?;
+        }
+        reporter.finish();
+    }
+    Ok(())
+}
+
+/// Run a future, also returning how long it took.
+async fn timed<F: Future>(future: F) -> (F::Output, Duration) {
+    let start = Instant::now();
+    let output = future.await;
+    (output, start.elapsed())
+}
+
+async fn check(mut args: CheckArgs) -> anyhow::Result<()> {
+    let check_start = Instant::now();
+    args.split_glob();
+    if let Some(path) = args.paths.iter().find(|path| !path.exists()) {
+        bail!("Path not found: {}", path.display());
+    }
+    let settings = Settings::load(args.settings.clone().map(|p| p.display().to_string()));
+    // Generate context
+    let context = Arc::new(SharedRuntimeContext::new(MergedSettings::new(
+        Box::new(args.clone()),
+        settings,
+    )));
+    let load_dictionaries_context = context.clone();
+    let dictionary_loader = task::spawn(timed(load_dictionaries(load_dictionaries_context)));
+    let (file_sender, file_receiver) = tokio::sync::mpsc::channel(256);
+    let file_loader = task::spawn({
+        let context = context.clone();
+        let args = args.clone();
+        timed(async move {
+            if args.diff_stdin {
+                let mut diff = String::new();
+                tokio::io::stdin()
+                    .read_to_string(&mut diff)
+                    .await
+                    .context("Failed to read diff from stdin")?;
+                let mut files = vec![];
+                for (path, source) in unified_diff::added_lines(&diff) {
+                    let path = context.settings.args.dir().join(path);
+                    let file = FileToCheck {
+                        path: path.clone(),
+                        source: Some(source),
+                    };
+                    file_sender.send(file).await.unwrap();
+                    files.push(path);
+                }
+                return anyhow::Ok((files, 0));
+            }
+            // Find files, also send them to file_sender
+            let paths: Box<dyn Iterator<Item = PathBuf> + Send> =
+                if args.staged || args.since.is_some() {
+                    Box::new(find_git_files(&args)?.into_iter())
+                } else {
+                    Box::new(explicit_files(&args).chain(walk_files(&args)))
+                };
+            let patterns = context
+                .settings
+                .settings
+                .glob_patterns(args.glob.as_deref())?;
+            let explicit = explicit_files(&args).collect::<HashSet<_>>();
+            let (mut files, mut excluded) = (vec![], 0);
+            for path in paths {
+                if !explicit.contains(&path) && !matches_any(&patterns, &path) {
+                    excluded += 1;
+                    continue;
+                }
+                let file = FileToCheck {
+                    path: path.clone(),
+                    source: None,
+                };
+                file_sender.send(file).await.unwrap();
+                files.push(path);
+            }
+            Ok((files, excluded))
+        })
+    });
+
+    let (res, files) = tokio::join!(dictionary_loader, file_loader);
+    let (res, dictionaries_elapsed) = res?;
+    res?;
+    let (files, finding_elapsed) = files?;
+    if args.unused_words.is_some() {
+        context.track_word_usage();
+    } else if !args.no_result_cache && dictionary::cache_enabled() {
+        // Cached results don't record which words were looked up, so the
+        // cache isn't used with `--unused-words`
+        let _ = context.results.set(ResultCache::load(
+            &cache_path(),
+            context.result_context_hash(),
+        ));
+    }
+    let (files, excluded) = files?;
+    let mut coverage = Coverage {
+        excluded,
+        ..Default::default()
+    };
+    if files.is_empty() {
+        if !args.quiet {
+            eprintln!("No files found");
+        }
+        if args.report_coverage {
+            coverage.print();
+        }
+        return Ok(());
+    }
+    let total_files = files.len();
+    if !args.quiet {
+        if total_files == 1 {
+            println!("Found 1 file");
+        } else {
+            println!("Found {total_files} files");
+        }
+    }
+
+    let (result_sender, mut result_receiver) = tokio::sync::mpsc::channel(256);
+    let file_receiver = Arc::new(Mutex::new(file_receiver));
+    let num_threads = context.settings.jobs();
+    if context.settings.verbose() {
+        if num_threads == 1 {
+            println!("Using 1 thread");
+        } else {
+            println!("Using {num_threads} threads");
+        }
+    }
+    let threads = (0..num_threads)
+        .map(|_| {
+            let context = context.clone();
+            let file_receiver = file_receiver.clone();
+            let result_sender = result_sender.clone();
+            thread::spawn(move || handle_file(context, file_receiver, result_sender))
+        })
+        .collect::<Vec<_>>();
+    drop(result_sender);
+    let output = context.settings.args.output().unwrap_or(OutputFormat::Text);
+    if matches!(&output, OutputFormat::Json) {
+        todo!();
+    }
+    let mut reporter = check_reporter(
+        &args,
+        total_files,
+        context.settings.verbose() || args.progress,
+    )?;
+    if let Some(ref path) = args.export_results {
+        reporter.export_to(path)?;
+    }
+    let language_map = &context.settings.settings.language_map;
+    let mut stopped = false;
+    let checking_start = Instant::now();
+    let mut checked = 0;
+    let mut last_reported = HashMap::default();
+    while let Some(mut result) = result_receiver.recv().await {
+        checked += 1;
+        let mut stop = false;
+        if args.fail_fast
+            && let Ok(ref mut typos) = result.typos
+            && !typos.is_empty()
+        {
+            typos.truncate(1);
+            stop = true;
+        }
+        reporter.report(&result)?;
+        if args.quiet_unchanged {
+            last_reported.insert(result.file.clone(), reported(&result));
+        }
+        coverage.add(
+            &result,
+            detect_language(&result.file, language_map).is_some(),
+        );
+        if stop {
+            context.cancelled.store(true, Ordering::Relaxed);
+            if !args.quiet {
+                println!("Stopped at the first typo");
+            }
+            stopped = true;
+            break;
+        }
+    }
+    drop(result_receiver);
+    let checking_elapsed = checking_start.elapsed();
+    let mut outcome = reporter.finish();
+    // Words may only be unused in the files that weren't checked
+    if let (Some(level), Some(usage), false) = (&args.unused_words, context.usage.get(), stopped) {
+        let unused = usage.unused();
+        for (name, words) in &unused {
+            if words.len() == 1 {
+                println!("{name}: 1 unused word");
+            } else {
+                println!("{name}: {} unused words", words.len());
+            }
+            for word in words {
+                println!("  {word}");
+            }
+        }
+        if *level == UnusedWords::Error && !unused.is_empty() {
+            outcome = outcome.max(Outcome::Typos);
+        }
+    }
+    if let Some(results) = context.results.get() {
+        if context.settings.verbose() {
+            println!("Reused the results of {} files", results.hits());
+        }
+        if let Err(e) = results.save() {
+            eprintln!("{e:#}");
+        }
+    }
+    if args.report_coverage {
+        coverage.print();
+    }
+    if args.time_report {
+        TimeReport {
+            dictionaries: dictionaries_elapsed,
+            finding: finding_elapsed,
+            checking: checking_elapsed,
+            total: check_start.elapsed(),
+            found: total_files,
+            excluded,
+            checked,
+        }
+        .print(context.settings.verbose());
+    }
+    if context.settings.verbose() {
+        println!("All files processed");
+    }
+    let start = Instant::now();
+    let mut printed = false;
+    loop {
+        let now = Instant::now();
+        // Kept off stdout, which may hold a JSON report
+        if !printed && !args.quiet && now - start > Duration::from_secs(1) {
+            eprintln!("Waiting for threads to finish...");
+            printed = true;
+        }
+        if now - start > Duration::from_secs(5) {
+            if !args.quiet {
+                eprintln!("Threads are taking too long to finish, exiting...");
+            }
+            std::process::exit(1);
+        }
+        if threads.iter().all(thread::JoinHandle::is_finished) {
+            break;
+        }
+    }
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    if args.watch {
+        return watch(context, &args, last_reported).await;
+    }
+    let code = args.exit_code_map.code(outcome);
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "lsp")]
+async fn serve_lsp(args: args::LspArgs) -> anyhow::Result<()> {
+    let settings = Settings::load(args.settings.clone().map(|p| p.display().to_string()));
+    let context = Arc::new(SharedRuntimeContext::new(MergedSettings::new(
+        Box::new(args),
+        settings,
+    )));
+    // Load once, up front, so every document is checked with the same tries
+    load_dictionaries(context.clone()).await?;
+    lsp::lsp(context).await;
+    Ok(())
+}
+
+async fn trace(args: &TraceArgs) -> anyhow::Result<()> {
+    let settings = Settings::load(args.settings.clone().map(|p| p.display().to_string()));
+    // Generate context
+    let context = Arc::new(SharedRuntimeContext::new(MergedSettings::new(
+        Box::new(args.clone()),
+        settings,
+    )));
+    let load_dictionaries_context = context.clone();
+    load_dictionaries(load_dictionaries_context).await?;
+    let mut found = false;
+    for kv in &context.dictionaries {
+        let name = kv.key();
+        let dict = kv.value();
+        if dict.contains(&args.word) {
+            println!("Found \'{}\' in dictionary {}", args.word, name);
+            found = true;
+        }
+    }
+    if !found {
+        println!("Did not find \'{}\' in any dictionary", args.word);
+    }
+    Ok(())
+}
+
+async fn cache(args: CacheCommand) -> anyhow::Result<()> {
+    match args {
+        CacheCommand::Build { prune_only } => {
+            if !prune_only {
+                for entry in fs::read_dir(store_path())? {
+                    // A bad entry doesn't keep the others from being built
+                    let dictionary = entry
+                        .map_err(anyhow::Error::from)
+                        .and_then(|entry| Dictionary::new_with_path(entry.path()));
+                    if let Err(e) = dictionary.and_then(|dictionary| dictionary.compile()) {
+                        eprintln!("Skipping dictionary: {e:#}");
+                    }
+                }
+            }
+            let cache_dir = cache_path();
+            let removed =
+                DictCacheStore::prune_at(&cache_dir)? + ResultCache::prune_at(&cache_dir)?;
+            if removed == 1 {
+                println!("Removed 1 orphaned cache entry");
+            } else {
+                println!("Removed {removed} orphaned cache entries");
+            }
+        }
+        CacheCommand::Clear => {
+            let cache_dir = cache_path();
+            if cache_dir.exists() {
+                tokio::fs::remove_dir_all(&cache_dir)
+                    .await
+                    .context(format!(
+                        "Failed to remove cache directory: {}",
+                        cache_dir.display()
+                    ))?;
+            } else {
+                eprintln!("Cache directory does not exist: {}", cache_dir.display());
+            }
+        }
+        CacheCommand::List => {
+            let cache_info = DictCacheStore::load_from_file(dict_cache_store_location()?)?;
+            for (key, entry) in &cache_info.0 {
+                println!("- {key}: {}", entry.source.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn dictionary(args: DictionaryCommand) -> anyhow::Result<()> {
+    match args {
+        DictionaryCommand::Diff(args) => {
+            let settings = Settings::load(args.settings.clone().map(|p| p.display().to_string()));
+            let load = |name: &str| -> anyhow::Result<Vec<Rule>> {
+                if args.compiled {
+                    let trie = load_dictionary(name, &settings)?
+                        .compile()
+                        .context(format!("Failed to compile dictionary: {name}"))?;
+                    return Ok(trie.to_rules());
+                }
+                load_dictionary_format_from_file(Path::new(name))
+                    .context(format!("Failed to load wordlist: {name}"))
+            };
+            let changes = diff_rules(&load(&args.old)?, &load(&args.new)?);
+            let (mut added, mut removed, mut changed) = (0, 0, 0);
+            for change in changes {
+                match change {
+                    RuleChange::Added(rule) => {
+                        added += 1;
+                        if !args.removed_only {
+                            println!("+ {rule}");
+                        }
+                    }
+                    RuleChange::Removed(rule) => {
+                        removed += 1;
+                        if !args.added_only {
+                            println!("- {rule}");
+                        }
+                    }
+                    RuleChange::Changed { old, new } => {
+                        changed += 1;
+                        if !args.added_only {
+                            println!("- {old}");
+                        }
+                        if !args.removed_only {
+                            println!("+ {new}");
+                        }
+                    }
+                }
+            }
+            println!("{added} added, {removed} removed, {changed} changed");
+        }
+    }
+    Ok(())
+}
+
+/// Problems with the settings at `path` that loading them would ignore or
+/// only run into later.
+fn settings_problems(path: &Path) -> anyhow::Result<Vec<String>> {
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read: {}", path.display()))?;
+    let value: serde_json::Value = match serde_hjson::from_str(&content) {
+        Ok(value) => value,
+        Err(e) => return Ok(vec![e.to_string()]),
+    };
+    let mut problems = settings::unknown_keys(&value)
+        .into_iter()
+        .map(|key| format!("Unknown key `{key}`"))
+        .collect::<Vec<_>>();
+    let settings = match Settings::load_from_file(path) {
+        Ok(settings) => settings,
+        Err(e) => {
+            problems.push(format!("{e:#}"));
+            return Ok(problems);
+        }
+    };
+    let root = std::env::current_dir()?;
+    for definition in &settings.dictionary_definitions {
+        // Git dictionaries are only cloned when first used
+        if let settings::CustomDictionaryDefinitionType::Path(_) = definition.typ {
+            let dictionary_path = root.join(definition.path());
+            if !dictionary_path.exists() {
+                problems.push(format!(
+                    "Path of dictionary `{}` not found: {}",
+                    definition.name,
+                    dictionary_path.display()
+                ));
+            }
+        }
+    }
+    let names = settings
+        .dictionaries
+        .iter()
+        .map(DictionaryName::name)
+        .chain(settings.translation_dictionaries.iter().cloned());
+    for name in names {
+        if find_dictionary(&name, &settings).is_err() {
+            problems.push(format!(
+                "Dictionary `{name}` is neither defined nor installed"
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// Report every problem with the settings, exiting with an error if there
+/// are any.
+fn validate(args: &ValidateArgs) -> anyhow::Result<()> {
+    let path = args
+        .settings
+        .clone()
+        .unwrap_or_else(settings::default_settings_path);
+    if !path.exists() {
+        bail!("Settings file not found: {}", path.display());
+    }
+    let problems = settings_problems(&path)?;
+    if problems.is_empty() {
+        println!("{} is valid", path.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}: {problem}", path.display());
+    }
+    if problems.len() == 1 {
+        eprintln!("Found 1 problem");
+    } else {
+        eprintln!("Found {} problems", problems.len());
+    }
+    std::process::exit(1);
+}
+
+/// Print the settings in effect, or write the default settings with `--init`.
+fn config(args: &ConfigArgs) -> anyhow::Result<()> {
+    let path = args
+        .settings
+        .clone()
+        .unwrap_or_else(settings::default_settings_path);
+    if args.init {
+        if path.exists() {
+            bail!("Settings file already exists: {}", path.display());
+        }
+        Settings::default()
+            .save_to_file(&path)
+            .context(format!("Failed to write settings: {}", path.display()))?;
+        println!("Wrote {}", path.display());
+        return Ok(());
+    }
+    let settings = Settings::load(Some(path.display().to_string()));
+    println!("{}", serde_json::to_string_pretty(&settings)?);
+    Ok(())
+}
+
+/// Compile a dictionary to make sure it's usable, reporting its word count
+/// and how long compiling took.
+fn check_dict(args: &CheckDictArgs) -> anyhow::Result<()> {
+    let settings = Settings::load(args.settings.clone().map(|p| p.display().to_string()));
+    let dictionary = load_dictionary(&args.name, &settings)?;
+    let start = Instant::now();
+    let compiled = dictionary.compile();
+    let elapsed = start.elapsed();
+    let output = args.output.clone().unwrap_or(OutputFormat::Text);
+    match (compiled, output) {
+        (Ok(trie), OutputFormat::Text | OutputFormat::Short) => {
+            println!(
+                "Compiled {}: {} words in {elapsed:.2?}",
+                args.name,
+                trie.to_vec().len()
+            );
+        }
+        (Ok(trie), OutputFormat::Json) => {
+            let report = serde_json::json!({
+                "name": args.name,
+                "words": trie.to_vec().len(),
+                "elapsed_ms": elapsed.as_secs_f64() * 1000.0,
+            });
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        (Err(e), OutputFormat::Text | OutputFormat::Short) => {
+            return Err(e.context(format!("Failed to compile dictionary: {}", args.name)));
+        }
+        (Err(e), OutputFormat::Json) => {
+            let report = serde_json::json!({
+                "name": args.name,
+                "error": format!("{e:#}"),
+                "elapsed_ms": elapsed.as_secs_f64() * 1000.0,
+            });
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Print the word count of each dictionary enabled in the settings and how
+/// many of its words no other enabled dictionary has, to find redundant
+/// dictionaries.
+fn stats(args: &StatsArgs) -> anyhow::Result<()> {
+    let settings = Settings::load(args.settings.clone().map(|p| p.display().to_string()));
+    let mut dictionaries = Vec::new();
+    for name in settings.dictionaries.iter().map(DictionaryName::name) {
+        let words = match load_dictionary(&name, &settings).and_then(|d| d.compile()) {
+            Ok(trie) => trie.to_vec(),
+            Err(e) => {
+                eprintln!("Skipping dictionary {name}: {e:#}");
+                continue;
+            }
+        };
+        dictionaries.push((name, words));
+    }
+    // The number of dictionaries each word is in
+    let mut occurrences: HashMap<&str, usize> = HashMap::default();
+    for (_, words) in &dictionaries {
+        for word in words {
+            *occurrences.entry(word).or_default() += 1;
+        }
+    }
+    let rows = dictionaries
+        .iter()
+        .map(|(name, words)| {
+            let unique = words
+                .iter()
+                .filter(|word| occurrences[word.as_str()] == 1)
+                .count();
+            (name, words.len(), unique)
+        })
+        .collect::<Vec<_>>();
+    match args.output.clone().unwrap_or(OutputFormat::Text) {
+        OutputFormat::Json => {
+            let rows = rows
+                .iter()
+                .map(|(name, words, unique)| {
+                    serde_json::json!({"name": name, "words": words, "unique": unique})
+                })
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+        }
+        OutputFormat::Text | OutputFormat::Short => {
+            let width = rows.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+            for (name, words, unique) in rows {
+                println!("{name:width$}  {words:>8} words  {unique:>8} unique");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn export(args: &ExportArgs) -> anyhow::Result<()> {
+    let settings = Settings::load(args.settings.clone().map(|p| p.display().to_string()));
+    let dictionary = load_dictionary(&args.name, &settings)?;
+    let trie = dictionary
+        .compile()
+        .context(format!("Failed to compile dictionary: {}", args.name))?;
+    if let Some(ref path) = args.out
+        && path
+            .extension()
+            .is_some_and(|extension| extension == "trie")
+    {
+        return cspell::CspellTrie::write_trie(&trie, path)
+            .context(format!("Failed to write trie to: {}", path.display()));
+    }
+    let rules = trie.to_rules();
+    let words = rules.iter().map(ToString::to_string).collect::<Vec<_>>();
+    let mut content = match args.output.clone().unwrap_or(OutputFormat::Text) {
+        OutputFormat::Json => serde_json::to_string_pretty(&words)?,
+        OutputFormat::Text | OutputFormat::Short => words.join("\n"),
+    };
+    content.push('\n');
+    match args.out {
+        Some(ref path) => fs::write(path, content)
+            .context(format!("Failed to write words to: {}", path.display()))?,
+        None => std::io::stdout().write_all(content.as_bytes())?,
+    }
+    Ok(())
+}
+
+async fn merge_results(args: &MergeResultsArgs) -> anyhow::Result<()> {
+    let mut results = Vec::new();
+    for file in &args.files {
+        results.extend(load_results(file)?);
+    }
+    let mut reporter = Reporter::new(results.len(), args.progress, args.group_by.clone());
+    for result in &results {
+        reporter.report(result)?;
+    }
+    if reporter.finish() != Outcome::Clean {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn install(args: &args::InstallArgs) -> anyhow::Result<()> {
+    // Try path
+    enum InstallType {
+        Path(PathBuf),
+        Url(Url),
+    }
+    let path = PathBuf::from(&args.uri);
+    let install_type = if path.exists() {
+        InstallType::Path(path)
+    } else {
+        InstallType::Url(Url::parse(&args.uri)?)
+    };
+    match install_type {
+        InstallType::Path(ref path) => {
+            tokio::fs::copy(path, store_path().join(path.file_name().unwrap())).await?;
+            Ok(())
+        }
+        InstallType::Url(ref url) => {
+            let content = download(url).await?;
+            let end = url
+                .path_segments()
+                .and_then(|mut s| s.next_back())
+                .unwrap_or_default();
+            if Path::new(end)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+            {
+                let zip_path = store_path().join(end);
+                if zip_path.exists() {
+                    if !args.yes {
+                        let confirm = Confirm::new("File already exists, overwrite?")
+                            .with_default(false)
+                            .prompt()?;
+                        if !confirm {
+                            println!("Aborting");
+                            return Ok(());
+                        }
+                    }
+                    if zip_path.is_dir() {
+                        tokio::fs::remove_dir_all(&zip_path).await.context(format!(
+                            "Failed to remove existing dir: {}",
+                            zip_path.display()
+                        ))?;
+                    } else {
+                        tokio::fs::remove_file(&zip_path).await.context(format!(
+                            "Failed to remove existing file: {}",
+                            zip_path.display()
+                        ))?;
+                    }
+                }
+                let mut file = fs::File::create(&zip_path)?;
+                file.write_all(&content)?;
+                let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path)?)?;
+                let base_out_path = store_path().join(
+                    url.path_segments()
+                        .unwrap()
+                        .next_back()
+                        .unwrap()
+                        .strip_suffix(".zip")
+                        .unwrap(),
+                );
+                // Check every entry before extracting any, so a malicious
+                // archive leaves nothing behind
+                let outpaths = (0..archive.len())
+                    .map(|i| {
+                        let name = archive.name_for_index(i).unwrap_or_default();
+                        zip_entry_path(&base_out_path, name)
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .inspect_err(|_| {
+                        let _ = fs::remove_file(&zip_path);
+                    })?;
+                for (i, outpath) in outpaths.into_iter().enumerate() {
+                    let mut file = archive.by_index(i)?;
+                    if file.is_dir() {
+                        fs::create_dir_all(&outpath)?;
+                    } else {
+                        if let Some(parent) = outpath.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        let mut outfile = fs::File::create(&outpath)?;
+                        std::io::copy(&mut file, &mut outfile)?;
+                    }
+                }
+                Ok(())
+            } else {
+                let path = store_path().join(url.path_segments().unwrap().next_back().unwrap());
+                if path == store_path() {
+                    bail!("Cannot install to cache directory");
+                }
+                if path.exists() {
+                    if !args.yes {
+                        let confirm = Confirm::new(&format!(
+                            "File {path} already exists, overwrite?",
+                            path = path.display()
+                        ))
+                        .with_default(false)
+                        .prompt()?;
+                        if !confirm {
+                            println!("Aborting");
+                            return Ok(());
+                        }
+                    }
+                    if path.is_dir() {
+                        fs::remove_dir_all(&path).context(format!(
+                            "Failed to remove existing dir: {}",
+                            path.display()
+                        ))?;
+                    } else {
+                        fs::remove_file(&path).context(format!(
+                            "Failed to remove existing file: {}",
+                            path.display()
+                        ))?;
+                    }
+                }
+                let mut file = fs::File::create(path)?;
+                file.write_all(&content)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Where to extract the zip entry `name` below `base`, refusing names that
+/// are absolute or contain `..`, which could write outside of it.
+fn zip_entry_path(base: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let mut path = base.to_path_buf();
+    // Archives made on Windows may use `\` as separator
+    for component in Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            std::path::Component::Normal(part) => path.push(part),
+            std::path::Component::CurDir => {}
+            _ => bail!("Refusing to extract archive entry outside of the dictionary: {name}"),
+        }
+    }
+    if !path.starts_with(base) || path == base {
+        bail!("Refusing to extract archive entry outside of the dictionary: {name}");
+    }
+    Ok(path)
+}
+
+/// Download a dictionary, retrying network errors and server errors with
+/// backoff.
+async fn download(url: &Url) -> anyhow::Result<Vec<u8>> {
+    const ATTEMPTS: u32 = 3;
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    if settings::offline() {
+        bail!("Failed to download dictionary from {url}: downloads are disabled by --offline");
+    }
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .context("Failed to create HTTP client")?;
+    let mut attempt = 1;
+    loop {
+        let error = match client.get(url.clone()).send().await {
+            Ok(response) if response.status().is_success() => match response.bytes().await {
+                Ok(content) => return Ok(content.to_vec()),
+                Err(e) => format!("{:#}", anyhow::Error::from(e)),
+            },
+            Ok(response) => {
+                let status = response.status();
+                // Other client errors won't go away by retrying
+                if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    bail!("Failed to download dictionary from {url}: server responded {status}");
+                }
+                format!("server responded {status}")
+            }
+            Err(e) if e.is_timeout() => format!("no response within {}s", TIMEOUT.as_secs()),
+            // Include the cause, like a refused connection
+            Err(e) => format!("{:#}", anyhow::Error::from(e)),
+        };
+        if attempt == ATTEMPTS {
+            bail!(
+                "Failed to download dictionary from {url}: {error}. Check your connection or \
+                 download the file and install it by path"
+            );
+        }
+        let backoff = Duration::from_secs(1 << attempt);
+        eprintln!(
+            "Failed to download dictionary from {url}: {error}, retrying in {}s",
+            backoff.as_secs()
+        );
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+    }
+}
+
+/// Run the command line interface with the arguments of the process.
+pub async fn run() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    settings::set_config_from_git_root(cli.config_from_git_root);
+    settings::set_offline(cli.offline);
+    dictionary::set_cache_enabled(!cli.no_cache);
+    if let Some(home) = cli.home {
+        filesystem::set_csc_home(home);
+    }
+
+    match cli.command {
+        CliArgs::Check(args) => {
+            check(*args).await?;
+        }
+        CliArgs::Trace(ref args) => {
+            trace(args).await?;
+        }
+        CliArgs::Cache(args) => {
+            cache(args).await?;
+        }
+        CliArgs::Lsp(args) => {
+            #[cfg(feature = "lsp")]
+            serve_lsp(args).await?;
+            #[cfg(not(feature = "lsp"))]
+            {
+                let _ = args;
+                eprintln!(
+                    "LSP support is not enabled. Please enable the 'lsp' feature when building."
+                );
+            }
+        }
+        CliArgs::Install(ref args) => {
+            install(args).await?;
+        }
+        CliArgs::ImportCspell(ref args) => {
+            cspell::import(args).await?;
+        }
+        CliArgs::ImportCspellConfig(ref args) => {
+            cspell::import_config(args)?;
+        }
+        CliArgs::Dictionary(args) => {
+            dictionary(args).await?;
+        }
+        CliArgs::Export(ref args) => {
+            export(args).await?;
+        }
+        CliArgs::CheckDict(ref args) => {
+            check_dict(args)?;
+        }
+        CliArgs::Stats(ref args) => {
+            stats(args)?;
+        }
+        CliArgs::Validate(ref args) => {
+            validate(args)?;
+        }
+        CliArgs::Config(ref args) => {
+            config(args)?;
+        }
+        CliArgs::MergeResults(ref args) => {
+            merge_results(args).await?;
+        }
+        CliArgs::Completions(ref args) => {
+            let mut command = Cli::command();
+            clap_complete::generate(
+                args.shell,
+                &mut command,
+                "cargo-csc",
+                &mut std::io::stdout(),
+            );
+        }
+        CliArgs::Man => {
+            clap_mangen::Man::new(Cli::command())
+                .render(&mut std::io::stdout())
+                .context("Failed to write the man page")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip_entry_path() {
+        let base = Path::new("/store/dictionary");
+        assert_eq!(
+            zip_entry_path(base, "words/en.txt").unwrap(),
+            base.join("words/en.txt")
+        );
+        assert_eq!(
+            zip_entry_path(base, "./csc-config.json").unwrap(),
+            base.join("csc-config.json")
+        );
+        for name in [
+            "../evil.txt",
+            "words/../../evil.txt",
+            "/etc/passwd",
+            "..\\evil.txt",
+            "",
+        ] {
+            assert!(zip_entry_path(base, name).is_err(), "{name}");
+        }
+    }
+}
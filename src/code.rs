@@ -1,90 +1,393 @@
 use std::{
     fmt::{Debug, Display, Formatter},
-    path::PathBuf,
+    ops::Range,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
-use miette::{Diagnostic, NamedSource, SourceOffset, SourceSpan};
+use anyhow::bail;
+use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceCode, SourceSpan};
+use serde::{Deserialize, Serialize};
 use tokio::{fs::File, io, io::AsyncReadExt};
 use tree_sitter::Node;
 
-pub async fn get_code(path: &PathBuf) -> anyhow::Result<(String, Option<tree_sitter::Parser>)> {
+use crate::HashMap;
+
+/// Map a file extension to the identifier of the grammar that handles it.
+fn language_from_extension(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "c" => "c",
+        "cpp" | "c++" => "cpp",
+        "go" => "go",
+        "html" => "html",
+        "js" => "javascript",
+        "json" | "jsonc" => "json",
+        "py" => "python",
+        "md" => "markdown",
+        "rb" => "ruby",
+        "rs" => "rust",
+        "toml" => "toml",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "yaml" | "yml" => "yaml",
+        _ => return None,
+    })
+}
+
+/// Map the interpreter of a shebang line, like `#!/usr/bin/env python3`, to
+/// a language identifier.
+fn language_from_shebang(line: &str) -> Option<&'static str> {
+    let mut command = line.strip_prefix("#!")?.split_whitespace();
+    let mut interpreter = command.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        // Skip options like `-S`
+        interpreter = command.find(|argument| !argument.starts_with('-'))?;
+    }
+    // Versioned interpreters like `python3.12`
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match name {
+        "python" => "python",
+        "node" | "nodejs" => "javascript",
+        "ruby" => "ruby",
+        _ => return None,
+    })
+}
+
+/// The language of a file without an extension, from its shebang line.
+fn language_from_first_line(path: &Path) -> Option<&'static str> {
+    use std::io::{BufRead, Read};
+
+    let file = std::fs::File::open(path).ok()?;
+    let mut line = String::new();
+    std::io::BufReader::new(file.take(256))
+        .read_line(&mut line)
+        .ok()?;
+    language_from_shebang(line.trim_end())
+}
+
+/// Get the tree-sitter grammar for a language identifier.
+fn get_language(language: &str) -> Option<tree_sitter::Language> {
+    Some(match language {
+        "c" => tree_sitter_c::LANGUAGE.into(),
+        "cpp" => tree_sitter_cpp::LANGUAGE.into(),
+        "go" => tree_sitter_go::LANGUAGE.into(),
+        "html" => tree_sitter_html::LANGUAGE.into(),
+        "javascript" => tree_sitter_javascript::LANGUAGE.into(),
+        "json" => tree_sitter_json::LANGUAGE.into(),
+        "python" => tree_sitter_python::LANGUAGE.into(),
+        "markdown" => tree_sitter_md::LANGUAGE.into(),
+        "ruby" => tree_sitter_ruby::LANGUAGE.into(),
+        "rust" => tree_sitter_rust::LANGUAGE.into(),
+        "toml" => tree_sitter_toml_ng::LANGUAGE.into(),
+        "typescript" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+        "tsx" => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        "yaml" => tree_sitter_yaml::LANGUAGE.into(),
+        _ => return None,
+    })
+}
+
+/// Detect the language of a file, consulting `language_map` (extension ->
+/// language) before the built-in table. Files without an extension are
+/// detected by their shebang line.
+pub fn detect_language(path: &Path, language_map: &HashMap<String, String>) -> Option<String> {
+    let Some(extension) = crate::filesystem::get_file_extension(path) else {
+        return language_from_first_line(path).map(ToString::to_string);
+    };
+    if let Some(language) = language_map.get(&extension) {
+        return Some(language.clone());
+    }
+    language_from_extension(&extension).map(ToString::to_string)
+}
+
+pub async fn get_code(
+    path: &PathBuf,
+    language_map: &HashMap<String, String>,
+) -> anyhow::Result<(String, Option<tree_sitter::Parser>)> {
     let file = File::open(path).await?;
     let mut reader = io::BufReader::new(file);
     let mut source_code = String::new();
     reader.read_to_string(&mut source_code).await?;
+    Ok((source_code, get_parser_for_path(path, language_map)?))
+}
+
+/// Create a parser for the language of `path`, or `None` if it has no
+/// grammar.
+pub fn get_parser_for_path(
+    path: &Path,
+    language_map: &HashMap<String, String>,
+) -> anyhow::Result<Option<tree_sitter::Parser>> {
+    let Some(language) = detect_language(path, language_map) else {
+        return Ok(None);
+    };
+    let Some(parser) = get_parser(&language)? else {
+        bail!("Unknown language `{language}` for file: {}", path.display());
+    };
+    Ok(Some(parser))
+}
+
+/// Create a parser for a language identifier, or `None` if the language is
+/// unknown.
+pub fn get_parser(language: &str) -> anyhow::Result<Option<tree_sitter::Parser>> {
+    let Some(language) = get_language(language) else {
+        return Ok(None);
+    };
     let mut parser = tree_sitter::Parser::new();
-    let mut found = true;
-    match crate::filesystem::get_file_extension(path)
-        .unwrap_or_default()
-        .as_str()
-    {
-        "c" => {
-            parser.set_language(&tree_sitter_c::LANGUAGE.into())?;
-        }
-        "cpp" | "c++" => {
-            parser.set_language(&tree_sitter_cpp::LANGUAGE.into())?;
-        }
-        "go" => {
-            parser.set_language(&tree_sitter_go::LANGUAGE.into())?;
-        }
-        "html" => {
-            parser.set_language(&tree_sitter_html::LANGUAGE.into())?;
-        }
-        "js" => {
-            parser.set_language(&tree_sitter_javascript::LANGUAGE.into())?;
-        }
-        "py" => {
-            parser.set_language(&tree_sitter_python::LANGUAGE.into())?;
-        }
-        "md" => {
-            parser.set_language(&tree_sitter_md::LANGUAGE.into())?;
-        }
-        "rb" => {
-            parser.set_language(&tree_sitter_ruby::LANGUAGE.into())?;
-        }
-        "rs" => {
-            parser.set_language(&tree_sitter_rust::LANGUAGE.into())?;
-        }
-        "toml" => {
-            parser.set_language(&tree_sitter_toml_ng::LANGUAGE.into())?;
-        }
-        "ts" => {
-            parser.set_language(&tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into())?;
+    parser.set_language(&language)?;
+    Ok(Some(parser))
+}
+
+/// Check a string for typos without any file IO.
+///
+/// `language` selects the grammar (e.g. `"rust"`); unknown or missing
+/// languages fall back to checking the text word by word.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use cargo_csc::{Dictionary, MultiTrie, check_text};
+///
+/// let dictionary = Dictionary::new_from_strings(&["hello".to_string(), "world".to_string()]);
+/// let mut words = MultiTrie::new();
+/// words.inner.push(Arc::new(dictionary.compile().unwrap()));
+///
+/// let typos = check_text("let hello = \"wrold\";", Some("rust"), &words);
+/// assert_eq!(typos.len(), 1);
+/// assert_eq!(typos[0].word, "wrold");
+/// ```
+pub fn check_text(text: &str, language: Option<&str>, words: &crate::MultiTrie) -> Vec<Typo> {
+    let source_code: Arc<str> = text.into();
+    let tree = language
+        .and_then(|language| get_parser(language).ok().flatten())
+        .and_then(|mut parser| parser.parse(text, None));
+    match tree {
+        Some(tree) => {
+            let skip = skipped_node_kinds(language.unwrap_or_default(), None)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            handle_node(words, &tree.root_node(), &source_code, &skip)
         }
-        "tsx" => {
-            parser.set_language(&tree_sitter_typescript::LANGUAGE_TSX.into())?;
+        None => handle_text(words, &source_code),
+    }
+}
+
+/// Directives that allow words for the rest of the file they appear in, e.g.
+/// `// cspell:words foo bar`.
+const INLINE_WORD_DIRECTIVES: [&str; 4] =
+    ["cspell:words", "cspell:ignore", "csc:words", "csc:ignore"];
+
+/// Collect the words allowed by inline directives in source code.
+pub fn inline_words(source_code: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for line in source_code.lines() {
+        for directive in INLINE_WORD_DIRECTIVES {
+            let Some(position) = line.find(directive) else {
+                continue;
+            };
+            let rest = &line[position + directive.len()..];
+            // Stop at the end of block comments
+            let rest = ["*/", "-->"]
+                .iter()
+                .filter_map(|end| rest.find(end))
+                .min()
+                .map_or(rest, |end| &rest[..end]);
+            words.extend(
+                rest.split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|word| !word.is_empty())
+                    .map(ToString::to_string),
+            );
         }
-        _ => {
-            found = false;
+    }
+    words
+}
+
+/// A kind of prose that checks can be restricted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prose {
+    Comments,
+    Strings,
+}
+
+/// Kinds of the nodes holding `prose` in a language's grammar, extended by
+/// `prose_node_kinds` in the settings.
+pub fn prose_node_kinds(language: &str, prose: Prose) -> &'static [&'static str] {
+    match (language, prose) {
+        ("rust", Prose::Comments) => &["line_comment", "block_comment"],
+        ("markdown", Prose::Comments) => &[],
+        (_, Prose::Comments) => &["comment"],
+        ("c" | "python" | "ruby" | "rust", Prose::Strings) => &["string_content"],
+        ("cpp", Prose::Strings) => &["string_content", "raw_string_content"],
+        ("go", Prose::Strings) => &[
+            "interpreted_string_literal_content",
+            "raw_string_literal_content",
+        ],
+        ("html", Prose::Strings) => &["text", "attribute_value"],
+        ("javascript" | "typescript" | "tsx", Prose::Strings) => &["string_fragment"],
+        ("json", Prose::Strings) => &["string_content"],
+        ("toml", Prose::Strings) => &["string"],
+        ("yaml", Prose::Strings) => &[
+            "string_scalar",
+            "double_quote_scalar",
+            "single_quote_scalar",
+            "block_scalar",
+        ],
+        (_, Prose::Strings) => &[],
+    }
+}
+
+/// Kinds of the nodes that are never checked in a language's grammar, or
+/// when checks are restricted to `prose`, extended by `skip_node_kinds` in
+/// the settings. Entries like `macro_invocation.macro` only skip the child in
+/// that field.
+pub fn skipped_node_kinds(language: &str, prose: Option<Prose>) -> &'static [&'static str] {
+    match (language, prose) {
+        // Keys are strings too, but not string values
+        ("json", Some(Prose::Strings)) => &["pair.key"],
+        ("yaml", Some(Prose::Strings)) => &["block_mapping_pair.key", "flow_pair.key"],
+        ("c" | "cpp", _) => &["preproc_include.path"],
+        ("go", _) => &["import_spec.path"],
+        ("html", _) => &["script_element", "style_element"],
+        ("javascript" | "typescript" | "tsx", _) => &["import_statement.source"],
+        ("markdown", _) => &["info_string"],
+        ("python", _) => &["import_statement", "import_from_statement"],
+        ("rust", _) => &[
+            "attribute_item",
+            "inner_attribute_item",
+            "macro_invocation.macro",
+        ],
+        _ => &[],
+    }
+}
+
+/// The children of `node` that aren't skipped, with everything under them.
+fn checked_children<'tree>(node: &Node<'tree>, skip: &[String]) -> Vec<Node<'tree>> {
+    let mut children = Vec::new();
+    for (index, child) in node.children(&mut node.walk()).enumerate() {
+        let skipped = skip.iter().any(|entry| match entry.split_once('.') {
+            Some((kind, field)) => {
+                node.kind() == kind && node.field_name_for_child(index as u32) == Some(field)
+            }
+            None => entry == child.kind(),
+        });
+        if !skipped {
+            children.push(child);
         }
     }
-    if !found {
-        Ok((source_code, None))
-    } else {
-        Ok((source_code, Some(parser)))
+    children
+}
+
+/// Check only the nodes under `node` whose kind is one of `kinds`, along
+/// with everything under them.
+pub fn handle_prose(
+    words: &crate::MultiTrie,
+    node: &Node,
+    source_code: &Arc<str>,
+    kinds: &[String],
+    skip: &[String],
+) -> Vec<Typo> {
+    if kinds.iter().any(|kind| kind == node.kind()) {
+        return handle_node(words, node, source_code, skip);
+    }
+    let mut typos = Vec::new();
+    for child in checked_children(node, skip) {
+        typos.append(&mut handle_prose(words, &child, source_code, kinds, skip));
+    }
+    typos
+}
+
+/// Kinds of string nodes that are prose even when they contain escapes, like
+/// Python's `string_content` around `\n` or `{{`.
+const STRING_CONTENT_KINDS: [&str; 3] =
+    ["string_content", "string_fragment", "double_quote_scalar"];
+
+/// Kinds of the code interpolated into strings, like `${name}` in JS/TS
+/// template literals or `{name}` in Python f-strings.
+const INTERPOLATION_KINDS: [&str; 2] = ["template_substitution", "interpolation"];
+
+/// The text of `node` with the children matching `blank` replaced by spaces,
+/// so they aren't read as part of the surrounding words.
+fn text_without(node: &Node, source_code: &str, blank: impl Fn(&Node) -> bool) -> String {
+    let mut text = source_code[node.start_byte()..node.end_byte()].to_string();
+    for child in node.children(&mut node.walk()) {
+        if blank(&child) {
+            let range =
+                child.start_byte() - node.start_byte()..child.end_byte() - node.start_byte();
+            text.replace_range(range.clone(), &" ".repeat(range.len()));
+        }
     }
+    text
+}
+
+/// Byte offset of `word`, a slice of `text`, in `text`.
+fn offset_in(text: &str, word: &str) -> usize {
+    word.as_ptr() as usize - text.as_ptr() as usize
+}
+
+/// Byte offset of `part`, as reported for `word`, in `word`. Parts may be
+/// lowercased, so case is ignored, and occurrences starting a part of the
+/// identifier are preferred, so that `or` in `for_or` isn't found in `for`.
+fn part_offset(word: &str, part: &str) -> usize {
+    let matches = (0..=word.len().saturating_sub(part.len()))
+        .filter(|&i| {
+            word.get(i..i + part.len())
+                .is_some_and(|candidate| candidate.eq_ignore_ascii_case(part))
+        })
+        .collect::<Vec<_>>();
+    let starts_part = |i: usize| {
+        let mut before = word[..i].chars().rev();
+        match (before.next(), word[i..].chars().next()) {
+            (None, _) => true,
+            (Some(prev), Some(next)) => {
+                !prev.is_alphabetic() || (prev.is_lowercase() && next.is_uppercase())
+            }
+            (Some(_), None) => false,
+        }
+    };
+    matches
+        .iter()
+        .copied()
+        .find(|&i| starts_part(i))
+        .or(matches.first().copied())
+        .unwrap_or(0)
 }
 
-pub fn handle_node(words: &crate::MultiTrie, node: &Node, source_code: &Arc<str>) -> Vec<Typo> {
-    let start_byte = node.start_byte();
-    let end_byte = node.end_byte();
-    let text = &source_code[start_byte..end_byte];
+/// Check the words under `node`, leaving out the nodes matching `skip`, as
+/// listed by [`skipped_node_kinds`].
+pub fn handle_node(
+    words: &crate::MultiTrie,
+    node: &Node,
+    source_code: &Arc<str>,
+    skip: &[String],
+) -> Vec<Typo> {
+    let is_string_content = node.child_count() > 0 && STRING_CONTENT_KINDS.contains(&node.kind());
+    let text = if is_string_content {
+        text_without(node, source_code, |_| true)
+    } else {
+        source_code[node.start_byte()..node.end_byte()].to_string()
+    };
     let mut typos = Vec::new();
-    if node.is_named() && node.child_count() == 0 {
+    // Tokens like Rust's `line_comment` only have anonymous children, such
+    // as `//`, and are checked whole
+    if node.is_named() && (node.named_child_count() == 0 || is_string_content) {
         for word in text.split_whitespace() {
-            if word.len() > 1 {
-                if let Some(typo) = words.handle_identifier(word) {
-                    // TODO: Fix
-                    // let suggestion = words.suggestion(&typo);
-                    let typo = Typo::from_node(typo, *node, source_code.clone(), None);
-                    typos.push(typo);
-                }
+            if word.len() > 1
+                && let Some(typo) = words.handle_identifier(word)
+            {
+                let suggestion = words.suggestion(&typo);
+                let category = words.category(&typo);
+                let offset = offset_in(&text, word) + part_offset(word, &typo);
+                let mut typo =
+                    Typo::from_node(typo, *node, offset, source_code.clone(), suggestion);
+                typo.category = category;
+                typos.push(typo);
             }
         }
     }
-    for child in node.children(&mut node.walk()) {
-        typos.append(&mut handle_node(words, &child, source_code));
+    // The escapes in string content were checked with it
+    if !is_string_content {
+        for child in checked_children(node, skip) {
+            typos.append(&mut handle_node(words, &child, source_code, skip));
+        }
     }
     // De-duplicate typos
     typos.dedup_by(|a, b| a.word == b.word && a.line == b.line && a.column == b.column);
@@ -95,17 +398,21 @@ pub fn handle_text(words: &crate::MultiTrie, source_code: &Arc<str>) -> Vec<Typo
     let mut typos = Vec::new();
     for (line_count, line) in source_code.lines().enumerate() {
         for word in line.split_whitespace() {
-            if word.len() > 1 {
-                if let Some(typo) = words.handle_identifier(word) {
-                    typos.push(Typo {
-                        line: line_count + 1,
-                        column: line.find(word).unwrap_or(0) + 1,
-                        length: word.len(),
-                        word: typo,
-                        suggestion: None,
-                        source: source_code.clone(),
-                    });
-                }
+            if word.len() > 1
+                && let Some(typo) = words.handle_identifier(word)
+            {
+                let suggestion = words.suggestion(&typo);
+                typos.push(Typo {
+                    line: line_count + 1,
+                    column: offset_in(line, word) + part_offset(word, &typo) + 1,
+                    length: typo.len(),
+                    category: words.category(&typo),
+                    word: typo,
+                    suggestion,
+                    cell: None,
+                    node_kind: None,
+                    source: source_code.clone(),
+                });
             }
         }
     }
@@ -114,74 +421,248 @@ pub fn handle_text(words: &crate::MultiTrie, source_code: &Arc<str>) -> Vec<Typo
     typos
 }
 
-#[derive(Clone, Debug)]
+/// Report deliberately obfuscated words (`l33t`, `passw0rd`) in the comments
+/// and strings under `node`.
+pub fn handle_leetspeak(
+    words: &crate::MultiTrie,
+    node: &Node,
+    source_code: &Arc<str>,
+) -> Vec<Typo> {
+    let kind = node.kind();
+    if !kind.contains("comment") && !kind.contains("string") {
+        let mut typos = Vec::new();
+        for child in node.children(&mut node.walk()) {
+            typos.append(&mut handle_leetspeak(words, &child, source_code));
+        }
+        return typos;
+    }
+    // Interpolated code isn't prose, but may hold strings of its own
+    let is_interpolation = |child: &Node| INTERPOLATION_KINDS.contains(&child.kind());
+    let text = text_without(node, source_code, is_interpolation);
+    let mut typos = text
+        .split_whitespace()
+        .filter_map(|word| {
+            let (typo, plain) = words.handle_leetspeak(word)?;
+            let offset = offset_in(&text, word) + part_offset(word, &typo);
+            Some(Typo::new_with_suggestion(
+                typo,
+                *node,
+                offset,
+                source_code.clone(),
+                plain,
+            ))
+        })
+        .collect::<Vec<_>>();
+    for child in node.children(&mut node.walk()) {
+        if is_interpolation(&child) {
+            typos.append(&mut handle_leetspeak(words, &child, source_code));
+        }
+    }
+    typos
+}
+
+/// Report deliberately obfuscated words in text that has no grammar.
+pub fn handle_leetspeak_text(words: &crate::MultiTrie, source_code: &Arc<str>) -> Vec<Typo> {
+    let mut typos = Vec::new();
+    for (line_count, line) in source_code.lines().enumerate() {
+        for word in line.split_whitespace() {
+            if let Some((typo, plain)) = words.handle_leetspeak(word) {
+                typos.push(Typo {
+                    line: line_count + 1,
+                    column: offset_in(line, word) + part_offset(word, &typo) + 1,
+                    length: typo.len(),
+                    word: typo,
+                    suggestion: Some(plain),
+                    category: TypoCategory::Unknown,
+                    cell: None,
+                    node_kind: None,
+                    source: source_code.clone(),
+                });
+            }
+        }
+    }
+    typos
+}
+
+/// Why a word was reported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TypoCategory {
+    /// Not in any dictionary, reported as a warning
+    #[default]
+    Unknown,
+    /// Forbidden by a dictionary with a leading `!`, reported as an error
+    Forbidden,
+}
+
+impl TypoCategory {
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::Unknown => Severity::Warning,
+            Self::Forbidden => Severity::Error,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Typo {
     pub line: usize,
     pub column: usize,
     pub length: usize,
     pub word: String,
     pub suggestion: Option<String>,
+    #[serde(default)]
+    pub category: TypoCategory,
+    /// The notebook cell the typo is in, counting from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cell: Option<usize>,
+    /// Kind of the tree-sitter node the typo is in, like `line_comment`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_kind: Option<String>,
+    #[serde(skip)]
     pub source: Arc<str>,
 }
 
 impl Typo {
+    /// A typo found `offset` bytes into `node`.
     fn from_node(
         word: String,
         node: Node,
+        offset: usize,
         source_code: Arc<str>,
         suggestion: Option<String>,
     ) -> Self {
-        let start_byte = node.start_byte();
-        let end_byte = node.end_byte();
-        let line = node.start_position().row + 1;
-        let column = node.start_position().column + 1;
-        let length = end_byte - start_byte;
+        let start = node.start_position();
+        let before = &source_code[node.start_byte()..node.start_byte() + offset];
+        let (line, column) = match before.rfind('\n') {
+            Some(i) => (start.row + before.matches('\n').count(), offset - i),
+            None => (start.row, start.column + offset + 1),
+        };
         Self {
-            line,
+            line: line + 1,
             column,
-            length,
+            length: word.len(),
             word,
             source: source_code,
             suggestion,
+            category: TypoCategory::Unknown,
+            cell: None,
+            node_kind: Some(node.kind().to_string()),
         }
     }
 
     pub fn new_with_suggestion(
         word: String,
         node: Node,
+        offset: usize,
         source_code: Arc<str>,
         suggestion: String,
     ) -> Self {
-        Self::from_node(word, node, source_code, Some(suggestion))
+        Self::from_node(word, node, offset, source_code, Some(suggestion))
     }
 
-    pub fn new_without_suggestion(word: String, node: Node, source_code: Arc<str>) -> Self {
-        Self::from_node(word, node, source_code, None)
+    pub fn new_without_suggestion(
+        word: String,
+        node: Node,
+        offset: usize,
+        source_code: Arc<str>,
+    ) -> Self {
+        Self::from_node(word, node, offset, source_code, None)
+    }
+
+    /// Where the typo is, as `file:line:column`, or `file: cell N, line M`
+    /// for notebooks.
+    pub fn location(&self, file: &str) -> String {
+        match self.cell {
+            Some(cell) => format!("{file}: cell {cell}, line {}", self.line),
+            None => format!("{file}:{}:{}", self.line, self.column),
+        }
+    }
+
+    /// The typo on one line, as `file:line:column: unknown word 'foo'`,
+    /// followed by ` did you mean 'bar'` when there's a suggestion.
+    pub fn to_short(&self, file: &str) -> String {
+        let kind = match self.category {
+            TypoCategory::Unknown => "unknown",
+            TypoCategory::Forbidden => "forbidden",
+        };
+        let location = self.location(file);
+        let mut line = format!("{location}: {kind} word '{}'", self.word);
+        if let Some(ref suggestion) = self.suggestion {
+            line.push_str(&format!(" did you mean '{suggestion}'"));
+        }
+        line
+    }
+
+    /// Name of the source shown in diagnostics, which for notebooks only
+    /// holds the typo's cell.
+    fn source_name(&self, file: &str) -> String {
+        match self.cell {
+            Some(cell) => format!("{file}, cell {cell}"),
+            None => file.to_string(),
+        }
+    }
+
+    /// Byte range of the typo in its source.
+    pub fn byte_range(&self) -> Range<usize> {
+        let line_start: usize = self
+            .source
+            .split_inclusive('\n')
+            .take(self.line.saturating_sub(1))
+            .map(str::len)
+            .sum();
+        let start = (line_start + self.column.saturating_sub(1)).min(self.source.len());
+        start..(start + self.length).min(self.source.len())
     }
 
     pub fn to_diagnostic(&self, file: &str) -> TypoDiagnostic {
-        let offset = SourceOffset::from_location(self.source.clone(), self.line, self.column);
-        let span = SourceSpan::new(offset, self.length);
+        // Columns are in bytes, while `SourceOffset::from_location` counts
+        // characters
+        let range = self.byte_range();
+        let span = SourceSpan::new(range.start.into(), range.len());
         let suggestion_text = match self.suggestion {
             Some(ref suggestion) => format!(" Did you mean `{}`?", suggestion),
             None => String::new(),
         };
+        let advice = match self.category {
+            TypoCategory::Unknown => format!("Unknown word `{}`.{suggestion_text}", self.word),
+            TypoCategory::Forbidden => format!("Forbidden word `{}`.{suggestion_text}", self.word),
+        };
         TypoDiagnostic {
-            src: NamedSource::new(file, self.source.clone()),
+            src: NamedSource::new(self.source_name(file), self.source.clone()),
             typo_span: span,
-            advice: format!("Unknown word `{}`.{}", self.word, suggestion_text),
+            advice,
+            severity: self.category.severity(),
         }
     }
 }
 
-#[derive(Clone, Diagnostic)]
+#[derive(Clone)]
 pub struct TypoDiagnostic {
-    #[source_code]
     src: NamedSource<Arc<str>>,
-    #[label = "Typo here"]
     typo_span: SourceSpan,
-    #[help]
     advice: String,
+    severity: Severity,
+}
+
+// Implemented by hand, as the derive only supports a fixed severity
+impl Diagnostic for TypoDiagnostic {
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(&self.advice))
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.src)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let label = LabeledSpan::new_with_span(Some("Typo here".to_string()), self.typo_span);
+        Some(Box::new(std::iter::once(label)))
+    }
 }
 
 impl Debug for TypoDiagnostic {
@@ -197,3 +678,216 @@ impl Display for TypoDiagnostic {
 }
 
 impl std::error::Error for TypoDiagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dictionary, MultiTrie};
+
+    fn words(words: &[&str]) -> MultiTrie {
+        let words = words.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let mut trie = MultiTrie::new();
+        trie.inner.push(Arc::new(
+            Dictionary::new_from_strings(&words).compile().unwrap(),
+        ));
+        trie
+    }
+
+    fn typo_words(typos: &[Typo]) -> Vec<&str> {
+        typos.iter().map(|typo| typo.word.as_str()).collect()
+    }
+
+    #[test]
+    fn test_template_literal() {
+        let words = words(&["hello", "there", "name", "let"]);
+        let typos = check_text("let s = `hello ${nmae} thre`;", Some("javascript"), &words);
+        assert_eq!(typo_words(&typos), ["nmae", "thre"]);
+    }
+
+    #[test]
+    fn test_f_string() {
+        let words = words(&["hello", "there", "name", "text"]);
+        let typos = check_text(
+            "text = f\"hello {nmae!r:>10} thre {{wrold}}\\nhelo\"\n",
+            Some("python"),
+            &words,
+        );
+        assert_eq!(typo_words(&typos), ["nmae", "thre", "wrold", "helo"]);
+    }
+
+    #[test]
+    fn test_forbidden_category() {
+        let words = words(&["hello", "!darn", "let"]);
+        let typos = check_text("let s = \"hello darn wrold\";", Some("rust"), &words);
+        let categories = typos
+            .iter()
+            .map(|typo| (typo.word.as_str(), typo.category))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            categories,
+            [
+                ("darn", TypoCategory::Forbidden),
+                ("wrold", TypoCategory::Unknown)
+            ]
+        );
+        assert_eq!(typos[0].suggestion, None);
+    }
+
+    #[test]
+    fn test_prose_only() {
+        let words = words(&["doc", "inner", "line", "let", "fn"]);
+        let source: Arc<str> =
+            "/// doc wrold\n//! inner thier\n// line commnet\nfn mian() { let s = \"strng\"; }\n"
+                .into();
+        let mut parser = get_parser("rust").unwrap().unwrap();
+        let tree = parser.parse(source.as_bytes(), None).unwrap();
+        let check = |prose| {
+            let kinds = prose_node_kinds("rust", prose)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            handle_prose(&words, &tree.root_node(), &source, &kinds, &[])
+                .into_iter()
+                .map(|typo| typo.word)
+                .collect::<Vec<_>>()
+        };
+        // Doc comments are checked along with line comments
+        assert_eq!(check(Prose::Comments), ["wrold", "thier", "commnet"]);
+        assert_eq!(check(Prose::Strings), ["strng"]);
+    }
+
+    /// Typos in the strings of `source`, leaving out keys.
+    fn string_typos(source: &str, language: &str, words: &MultiTrie) -> Vec<String> {
+        let source: Arc<str> = source.into();
+        let mut parser = get_parser(language).unwrap().unwrap();
+        let tree = parser.parse(source.as_bytes(), None).unwrap();
+        let to_strings = |kinds: &[&str]| kinds.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let kinds = to_strings(prose_node_kinds(language, Prose::Strings));
+        let skip = to_strings(skipped_node_kinds(language, Some(Prose::Strings)));
+        handle_prose(words, &tree.root_node(), &source, &kinds, &skip)
+            .into_iter()
+            .map(|typo| typo.word)
+            .collect()
+    }
+
+    #[test]
+    fn test_json() {
+        let words = words(&["name", "hello", "world", "count"]);
+        let source =
+            "// commnet\n{\"naem\": \"helo\\nwrold\", \"count\": [1, {\"nmae\": \"wolrd\"}]}\n";
+        let typos = check_text(source, Some("json"), &words);
+        assert_eq!(
+            typo_words(&typos),
+            ["commnet", "naem", "helo", "wrold", "nmae", "wolrd"]
+        );
+        assert_eq!(
+            string_typos(source, "json", &words),
+            ["helo", "wrold", "wolrd"]
+        );
+    }
+
+    #[test]
+    fn test_yaml() {
+        let words = words(&["name", "hello", "world", "items", "say", "block"]);
+        let source = "# commnet\nnaem: helo wrold\nsay: \"helo\\nwolrd\"\nitems:\n  - 3\n  - {nmae: 'wrod'}\nblock: |\n  hello wordl\n";
+        let typos = check_text(source, Some("yaml"), &words);
+        assert_eq!(
+            typo_words(&typos),
+            [
+                "commnet", "naem", "helo", "wrold", "helo", "wolrd", "nmae", "wrod", "wordl"
+            ]
+        );
+        assert_eq!(
+            string_typos(source, "yaml", &words),
+            ["helo", "wrold", "helo", "wolrd", "wrod", "wordl"]
+        );
+    }
+
+    #[test]
+    fn test_language_from_shebang() {
+        assert_eq!(
+            language_from_shebang("#!/usr/bin/env python3"),
+            Some("python")
+        );
+        assert_eq!(
+            language_from_shebang("#!/usr/bin/python3.12 -u"),
+            Some("python")
+        );
+        assert_eq!(
+            language_from_shebang("#!/usr/bin/env node"),
+            Some("javascript")
+        );
+        assert_eq!(
+            language_from_shebang("#!/usr/bin/env -S node --harmony"),
+            Some("javascript")
+        );
+        assert_eq!(language_from_shebang("#!/bin/bash"), None);
+        assert_eq!(language_from_shebang("print('hi')"), None);
+    }
+
+    #[test]
+    fn test_skipped_node_kinds() {
+        let words = words(&["hello", "world", "feature", "let", "fn"]);
+        let source = "#[cfg(featre = \"lsp\")]\nfn mian() { prntln!(\"helo world\"); }\n";
+        let typos = check_text(source, Some("rust"), &words);
+        // The attribute and the macro's name are skipped, but not its arguments
+        assert_eq!(typo_words(&typos), ["mian", "helo"]);
+    }
+
+    #[test]
+    fn test_short() {
+        let words = words(&["hello", "world", "!darn", "let"]);
+        let typos = check_text(
+            "let s = \"hello darn\";\nlet t = \"wrold\";",
+            Some("rust"),
+            &words,
+        );
+        let lines = typos
+            .iter()
+            .map(|typo| {
+                let mut typo = typo.clone();
+                if typo.word == "wrold" {
+                    typo.suggestion = Some("world".to_string());
+                }
+                typo.to_short("main.rs")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "main.rs:1:16: forbidden word 'darn'",
+                "main.rs:2:10: unknown word 'wrold' did you mean 'world'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multi_byte_column() {
+        let words = words(&["café", "fn", "main"]);
+        let source = "// café wrold\nfn main() {} // café café wrold\n";
+        for typo in check_text(source, Some("rust"), &words)
+            .iter()
+            .chain(&check_text(source, None, &words))
+        {
+            assert_eq!(&source[typo.byte_range()], "wrold");
+            let span = typo.to_diagnostic("test.rs").typo_span;
+            assert_eq!(&source[span.offset()..span.offset() + span.len()], "wrold");
+        }
+    }
+
+    #[test]
+    fn test_repeated_typo() {
+        let words = words(&["others", "fn", "main"]);
+        let source = "fn main() {} // others ther,\n// ther others\n";
+        for typos in [
+            check_text(source, Some("rust"), &words),
+            check_text(source, None, &words),
+        ] {
+            let ranges = typos
+                .iter()
+                .map(|typo| (typo.word.as_str(), typo.byte_range()))
+                .collect::<Vec<_>>();
+            assert_eq!(ranges, [("ther", 23..27), ("ther", 32..36)]);
+        }
+    }
+}
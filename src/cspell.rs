@@ -1,20 +1,164 @@
 mod trie;
 
-use std::{fs, io::Write};
+use std::{collections::BTreeMap, fs, io::Write, path::PathBuf, sync::Arc};
 
 use anyhow::{Context, anyhow};
 use git2::Repository;
-use tokio::task::JoinSet;
-pub use trie::CspellTrie;
+use serde::Deserialize;
+use tokio::{sync::Semaphore, task::JoinSet};
+pub use trie::{CspellTrie, is_trie_file};
 
 use crate::{
+    HashSet,
+    args::{ImportCspellArgs, ImportCspellConfigArgs},
     dictionary,
-    filesystem::{cspell_path, store_path},
+    filesystem::{cspell_path, get_path_hash, store_path},
+    settings::{DictionaryName, Settings, default_settings_path},
 };
 
-const URL: &str = "https://github.com/arihant2math/cspell-dicts";
+pub const DEFAULT_REPO: &str = "https://github.com/arihant2math/cspell-dicts";
+/// Maximum number of word files installed at once, to stay within the open
+/// file limit.
+const MAX_CONCURRENT_COPIES: usize = 16;
 
-pub async fn import() -> anyhow::Result<()> {
+/// Trim the lines of a word list and drop empty lines and duplicate words,
+/// lowercasing words unless the dictionary is case sensitive. Returns the
+/// normalized list and the number of lines removed.
+fn normalize_word_list(content: &str, case_sensitive: bool) -> (String, usize) {
+    let mut seen = HashSet::default();
+    let mut normalized = String::with_capacity(content.len());
+    let mut removed = 0;
+    for line in content.lines() {
+        let line = line.trim();
+        let line = if case_sensitive || line.starts_with('#') || line.starts_with("//") {
+            line.to_string()
+        } else {
+            line.to_lowercase()
+        };
+        if line.is_empty() || !seen.insert(line.clone()) {
+            removed += 1;
+            continue;
+        }
+        normalized.push_str(&line);
+        normalized.push('\n');
+    }
+    (normalized, removed)
+}
+
+/// Copy a word file into the store, normalizing it if `normalize` is set,
+/// unless the store already has the same content. Returns whether the file
+/// was written and the number of lines removed by normalization.
+async fn install_word_file(
+    src: PathBuf,
+    dst: PathBuf,
+    normalize: bool,
+    case_sensitive: bool,
+) -> anyhow::Result<(bool, usize)> {
+    let is_word_list = src.extension().is_some_and(|extension| extension == "txt");
+    if !normalize || !is_word_list {
+        if dst.exists() && get_path_hash(&src)? == get_path_hash(&dst)? {
+            return Ok((false, 0));
+        }
+        tokio::fs::copy(&src, &dst)
+            .await
+            .context(format!("Failed to copy: {}", src.display()))?;
+        return Ok((true, 0));
+    }
+    let content = tokio::fs::read_to_string(&src)
+        .await
+        .context(format!("Failed to read: {}", src.display()))?;
+    let (content, removed) = normalize_word_list(&content, case_sensitive);
+    if tokio::fs::read_to_string(&dst).await.ok().as_ref() == Some(&content) {
+        return Ok((false, removed));
+    }
+    tokio::fs::write(&dst, content)
+        .await
+        .context(format!("Failed to write: {}", dst.display()))?;
+    Ok((true, removed))
+}
+
+/// The fields of a cspell configuration that have an equivalent in the
+/// settings.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CspellConfig {
+    #[serde(default)]
+    words: Vec<String>,
+    #[serde(default)]
+    ignore_words: Vec<String>,
+    #[serde(default)]
+    flag_words: Vec<String>,
+    #[serde(default)]
+    dictionaries: Vec<String>,
+    #[serde(default)]
+    ignore_paths: Vec<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Fields of a cspell configuration that don't need an equivalent.
+const IGNORED_CONFIG_FIELDS: [&str; 2] = ["$schema", "version"];
+
+/// Add the fields of a cspell configuration to `settings`, warning about
+/// fields that can't be imported.
+fn merge_config(settings: &mut Settings, config: CspellConfig) {
+    let words = config
+        .words
+        .into_iter()
+        .chain(config.ignore_words)
+        .chain(config.flag_words.into_iter().map(|word| format!("!{word}")));
+    for word in words {
+        if !settings.words.contains(&word) {
+            settings.words.push(word);
+        }
+    }
+    for name in config.dictionaries {
+        if !settings
+            .dictionaries
+            .iter()
+            .any(|dictionary| dictionary.name() == name)
+        {
+            settings.dictionaries.push(DictionaryName::Simple(name));
+        }
+    }
+    for path in config.ignore_paths {
+        if !settings.ignore_paths.contains(&path) {
+            settings.ignore_paths.push(path);
+        }
+    }
+    for field in config.other.keys() {
+        if !IGNORED_CONFIG_FIELDS.contains(&field.as_str()) {
+            eprintln!("Skipping `{field}`, which has no equivalent in the settings");
+        }
+    }
+}
+
+/// Import a cspell configuration into the settings file.
+pub fn import_config(args: &ImportCspellConfigArgs) -> anyhow::Result<()> {
+    let content = fs::read_to_string(&args.path)
+        .context(format!("Failed to read: {}", args.path.display()))?;
+    // cspell allows comments in its JSON configuration
+    let config: CspellConfig = serde_hjson::from_str(&content).context(format!(
+        "Invalid cspell configuration: {}",
+        args.path.display()
+    ))?;
+    let out = args.out.clone().unwrap_or_else(default_settings_path);
+    let mut settings = if out.exists() {
+        Settings::load_from_file(&out)
+            .context(format!("Failed to load settings: {}", out.display()))?
+    } else {
+        Settings::default()
+    };
+    merge_config(&mut settings, config);
+    settings
+        .save_to_file(&out)
+        .context(format!("Failed to write settings: {}", out.display()))?;
+    println!("Imported {} into {}", args.path.display(), out.display());
+    Ok(())
+}
+
+pub async fn import(args: &ImportCspellArgs) -> anyhow::Result<()> {
+    let url = args.cspell_repo.as_str();
     let repo_path = cspell_path().join("cspell-dicts");
     if !repo_path.exists() {
         tokio::fs::create_dir_all(&repo_path)
@@ -24,12 +168,17 @@ pub async fn import() -> anyhow::Result<()> {
                 repo_path.display()
             ))?;
 
-        println!("Cloning {URL}");
-        crate::git::clone(URL, &repo_path).with_context(|| format!("failed to clone: {URL}"))?;
+        println!("Cloning {url}");
+        crate::git::clone(url, &repo_path, args.depth)
+            .with_context(|| format!("failed to clone: {url}"))?;
     } else {
         let res = Repository::open(&repo_path);
         match res {
             Ok(repo) => {
+                // Follow the configured repository if it changed since cloning
+                if repo.find_remote("origin")?.url() != Some(url) {
+                    repo.remote_set_url("origin", url)?;
+                }
                 // Update repo
                 let mut remote = repo.find_remote("origin")?;
                 let remote_branch = "main";
@@ -41,15 +190,19 @@ pub async fn import() -> anyhow::Result<()> {
                 eprintln!("Failed to open temporary directory: {e}");
                 // Reclone
                 tokio::fs::remove_dir_all(&repo_path).await?;
-                println!("Recloning {URL}");
-                crate::git::clone(URL, &repo_path)
-                    .with_context(|| format!("failed to clone: {URL}"))?;
+                println!("Recloning {url}");
+                crate::git::clone(url, &repo_path, args.depth)
+                    .with_context(|| format!("failed to clone: {url}"))?;
             }
         }
     }
 
     println!("Installing cspell dictionaries");
     let dicts_root = repo_path.join("dictionaries");
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_COPIES));
+    // Copies of every dictionary, tagged with the dictionary's index
+    let mut futures = JoinSet::new();
+    let mut dictionaries = Vec::new();
 
     for entry in fs::read_dir(&dicts_root)? {
         let entry = entry?;
@@ -62,20 +215,18 @@ pub async fn import() -> anyhow::Result<()> {
             for file_entry in fs::read_dir(&dict_subdir)? {
                 let file_entry = file_entry?;
                 let p = file_entry.path();
-                if let Some(fname) = p.file_name().and_then(|s| s.to_str()) {
-                    if glob::Pattern::new("*.txt")?.matches(fname) {
-                        files.push(p.canonicalize()?);
-                    }
+                if let Some(fname) = p.file_name().and_then(|s| s.to_str())
+                    && glob::Pattern::new("*.txt")?.matches(fname)
+                {
+                    files.push(p.canonicalize()?);
                 }
             }
         }
         for file_entry in fs::read_dir(&dict_dir)? {
             let file_entry = file_entry?;
             let p = file_entry.path();
-            if let Some(fname) = p.file_name().and_then(|s| s.to_str()) {
-                if glob::Pattern::new("*.trie")?.matches(fname) {
-                    files.push(p.canonicalize()?);
-                }
+            if is_trie_file(&p) {
+                files.push(p.canonicalize()?);
             }
         }
         if files.is_empty() {
@@ -86,11 +237,6 @@ pub async fn import() -> anyhow::Result<()> {
             "cspell_{}",
             dict_dir.file_name().unwrap().to_string_lossy()
         ));
-        if store.exists() {
-            tokio::fs::remove_dir_all(&store)
-                .await
-                .context(format!("Failed to remove directory: {}", store.display()))?;
-        }
         tokio::fs::create_dir_all(&store)
             .await
             .context(format!("Failed to create directory: {}", store.display()))?;
@@ -108,8 +254,7 @@ pub async fn import() -> anyhow::Result<()> {
             globs: Vec::new(),
         };
 
-        let mut futures = JoinSet::new();
-
+        let index = dictionaries.len();
         for src in files {
             let file_name = src
                 .file_name()
@@ -117,31 +262,126 @@ pub async fn import() -> anyhow::Result<()> {
                 .to_string_lossy()
                 .into_owned();
             let dst = store.join(&file_name);
-            let copy_future = tokio::fs::copy(src, dst);
-            futures.spawn(copy_future);
+            let semaphore = semaphore.clone();
+            let (normalize, case_sensitive) = (args.normalize, config.case_sensitive);
+            futures.spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                let installed = install_word_file(src, dst, normalize, case_sensitive).await?;
+                anyhow::Ok((index, installed))
+            });
             config.paths.push(file_name);
         }
-        // Wait for all copy operations to complete
-        let output = futures.join_all().await;
-        for res in output {
-            res?;
+        dictionaries.push((store, config));
+    }
+
+    // Wait for all copy operations to complete
+    let mut installed = vec![(false, 0); dictionaries.len()];
+    while let Some(res) = futures.join_next().await {
+        let (index, (written, removed_lines)) = res??;
+        installed[index].0 |= written;
+        installed[index].1 += removed_lines;
+    }
+
+    let total = dictionaries.len();
+    let mut updated = 0;
+    for ((store, config), (mut changed, removed)) in dictionaries.into_iter().zip(installed) {
+        // Remove files that are no longer part of the dictionary
+        for file_entry in fs::read_dir(&store)? {
+            let p = file_entry?.path();
+            let file_name = p.file_name().unwrap_or_default().to_string_lossy();
+            if file_name != "csc-config.json" && !config.paths.iter().any(|path| *path == file_name)
+            {
+                tokio::fs::remove_file(&p)
+                    .await
+                    .context(format!("Failed to remove: {}", p.display()))?;
+                changed = true;
+            }
         }
         // Write the config file
         let config_path = store.join("csc-config.json");
         let config_content =
             serde_json::to_string_pretty(&config).context("Failed to serialize config")?;
-        let mut config_file = fs::File::create(&config_path).context(format!(
-            "Failed to create config file: {}",
-            config_path.display()
-        ))?;
-        config_file
-            .write(config_content.as_bytes())
-            .context(format!(
-                "Failed to write config file: {}",
+        if fs::read_to_string(&config_path).ok() != Some(config_content.clone()) {
+            let mut config_file = fs::File::create(&config_path).context(format!(
+                "Failed to create config file: {}",
                 config_path.display()
             ))?;
+            config_file
+                .write(config_content.as_bytes())
+                .context(format!(
+                    "Failed to write config file: {}",
+                    config_path.display()
+                ))?;
+            changed = true;
+        }
+        if !changed {
+            continue;
+        }
+        updated += 1;
 
-        println!("Installed dictionary: {}", config.name);
+        if args.normalize {
+            println!(
+                "Installed dictionary: {} ({removed} entries removed)",
+                config.name
+            );
+        } else {
+            println!("Installed dictionary: {}", config.name);
+        }
     }
+    println!("Updated {updated} of {total} dictionaries");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_config() {
+        let config: CspellConfig = serde_hjson::from_str(
+            r#"{
+                // Comments are allowed
+                "version": "0.2",
+                "language": "en",
+                "words": ["foo", "bar"],
+                "ignoreWords": ["baz"],
+                "flagWords": ["hte"],
+                "dictionaries": ["rust", "words"],
+                "ignorePaths": ["target/**"]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.other.keys().collect::<Vec<_>>(),
+            ["language", "version"]
+        );
+        let mut settings = Settings {
+            words: vec!["foo".to_string()],
+            ..Settings::default()
+        };
+        merge_config(&mut settings, config);
+        assert_eq!(settings.words, ["foo", "bar", "baz", "!hte"]);
+        let dictionaries = settings
+            .dictionaries
+            .iter()
+            .map(DictionaryName::name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dictionaries.iter().filter(|name| *name == "words").count(),
+            1
+        );
+        assert!(dictionaries.contains(&"rust".to_string()));
+        assert_eq!(settings.ignore_paths, ["target/**"]);
+    }
+
+    #[test]
+    fn test_normalize_word_list() {
+        let (normalized, removed) =
+            normalize_word_list("# Words\nHello \n\nhello\nworld\n  World\n", false);
+        assert_eq!(normalized, "# Words\nhello\nworld\n");
+        assert_eq!(removed, 3);
+        let (normalized, removed) = normalize_word_list("Hello\nhello\nHello\n", true);
+        assert_eq!(normalized, "Hello\nhello\n");
+        assert_eq!(removed, 1);
+    }
+}
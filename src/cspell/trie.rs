@@ -3,6 +3,7 @@ mod spec;
 
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
+pub use spec::is_trie_file;
 
 use crate::Trie;
 
@@ -13,7 +14,6 @@ trait CspellTrieVersion {
     fn write(trie: &Trie) -> anyhow::Result<Vec<String>>;
 }
 
-#[expect(dead_code)]
 struct V3;
 
 impl CspellTrieVersion for V3 {
@@ -22,12 +22,11 @@ impl CspellTrieVersion for V3 {
         Ok(res.1)
     }
 
-    fn write(_trie: &Trie) -> anyhow::Result<Vec<String>> {
-        todo!()
+    fn write(trie: &Trie) -> anyhow::Result<Vec<String>> {
+        Ok(spec::write_v3(trie))
     }
 }
 
-#[expect(unused)]
 struct V4;
 
 impl CspellTrieVersion for V4 {
@@ -59,8 +58,17 @@ pub struct CspellTrie;
 
 impl CspellTrie {
     pub fn parse_trie<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Trie> {
+        crate::filesystem::ensure_not_lfs_pointer(&path)?;
         let converted = spec::file_to_lines(path)?;
         let (_, trie) = spec::parse_trie(converted.as_slice())?;
         Ok(trie)
     }
+
+    /// Write the allowed words of `trie` to `path` as a `TrieXv3` trie.
+    pub fn write_trie<P: AsRef<std::path::Path>>(trie: &Trie, path: P) -> anyhow::Result<()> {
+        let mut content = V3::write(trie)?.join("\n");
+        content.push('\n');
+        std::fs::write(path, content)?;
+        Ok(())
+    }
 }
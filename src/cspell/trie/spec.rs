@@ -1,27 +1,28 @@
-use std::{cell::RefCell, collections::HashMap, io::Read, rc::Rc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+};
 
 use flate2::bufread::GzDecoder;
+use anyhow::{Context, bail};
 use fst::MapBuilder;
-use crate::{Trie, trie::TrieOptions};
+use crate::{Trie, dictionary::Rule, trie::TrieOptions};
 
 #[derive(Debug)]
 struct Version(#[allow(dead_code)] pub String);
 
 impl Version {
-    // TODO: Should be result due to unwrap
-    #[expect(dead_code)]
-    pub fn to_u8(&self) -> u8 {
+    pub fn to_u8(&self) -> anyhow::Result<u8> {
         self.0
             .split('v')
             .next_back()
             .and_then(|s| s.parse::<u8>().ok())
-            .unwrap()
+            .ok_or_else(|| anyhow::anyhow!("Invalid cspell trie version: {}", self.0))
     }
 }
 
 #[derive(Debug)]
 pub struct Header {
-    #[expect(unused)]
     version: Version,
     base: u8,
 }
@@ -49,18 +50,25 @@ fn parse_header(input: &[String]) -> anyhow::Result<(usize, Header)> {
             base = Some(rest.parse::<u8>()?);
         }
     }
+    let Some(version) = version else {
+        bail!("Missing `TrieXv` version line in cspell trie header");
+    };
+    let Some(base) = base else {
+        bail!("Missing `base=` line in cspell trie header");
+    };
     Ok((
         counter,
         Header {
-            version: Version(version.unwrap()),
-            base: base.unwrap(),
+            version: Version(version),
+            base,
         },
     ))
 }
 
 struct TrieNode {
     eow: bool,
-    children: HashMap<char, Rc<RefCell<TrieNode>>>,
+    /// Indices of the children in [`TrieBuilder::nodes`]
+    children: HashMap<char, usize>,
 }
 
 /// Internal parse states.
@@ -75,21 +83,26 @@ enum ParseState {
 }
 
 /// Helper struct that builds a trie.
+///
+/// Nodes refer to each other by index, so references that make the graph
+/// cyclic are freed with the builder.
 struct TrieBuilder {
     /// Flat storage of nodes (for reference indexing).
-    nodes: Vec<Rc<RefCell<TrieNode>>>,
-    /// Current path in the tree.
-    pos: Vec<Rc<RefCell<TrieNode>>>,
+    nodes: Vec<TrieNode>,
+    /// Current path in the tree, as indices into `nodes`.
+    pos: Vec<usize>,
     pos_string: String,
+    /// Print every step of the parse, enabled by setting `CSC_TRIE_TRACE`
+    trace: bool,
 }
 
 impl TrieBuilder {
     fn new() -> Self {
-        let root = Rc::new(RefCell::new(TrieNode::new_root()));
         Self {
-            nodes: vec![root.clone()],
-            pos: vec![root],
+            nodes: vec![TrieNode::new_root()],
+            pos: vec![0],
             pos_string: String::new(),
+            trace: std::env::var_os("CSC_TRIE_TRACE").is_some(),
         }
     }
 
@@ -101,26 +114,12 @@ impl TrieBuilder {
             let pos_pos = self
                 .pos
                 .iter()
-                .position(|p| Rc::ptr_eq(p, node))
+                .position(|&p| p == i)
                 .map(i64::try_from)
                 .unwrap_or(Ok(-1))
                 .unwrap();
-            let node_borrow = node.borrow();
-            let mut child_ids: Vec<_> = node_borrow
-                .children
-                .iter()
-                .map(|(&ch, v)| {
-                    // Find position in nodes
-                    (
-                        ch,
-                        self.nodes
-                            .iter()
-                            .position(|p| Rc::ptr_eq(p, v))
-                            .unwrap_or(usize::MAX),
-                    )
-                })
-                .collect();
-            child_ids.sort_by(|a, b| a.1.cmp(&b.1));
+            let mut child_ids: Vec<_> = node.children.iter().map(|(&ch, &v)| (ch, v)).collect();
+            child_ids.sort_by_key(|a| a.1);
             let children = child_ids
                 .iter()
                 .map(|(chr, v)| v.to_string() + "=" + &chr.to_string())
@@ -129,56 +128,61 @@ impl TrieBuilder {
             println!(
                 "{pos_pos:>2}  ID {:>3}: {} children={}",
                 i,
-                bstr(node_borrow.eow),
+                bstr(node.eow),
                 children
             );
         }
     }
 
     /// Absolute jump to a node in the trie.
-    fn jump_to(&mut self, idx: usize) {
-        let top = self.pos.last().unwrap();
-        let p = self.pos[self.pos.len() - 2].clone();
-        let mut p_mut = p.borrow_mut();
-        p_mut.children.insert(self.pos_string.chars().last().unwrap(), self.nodes[idx].clone());
+    fn jump_to(&mut self, idx: usize) -> anyhow::Result<()> {
+        if idx >= self.nodes.len() {
+            bail!("Reference out of bounds: {}", idx - 1);
+        }
+        let (Some(p), Some(c)) = (
+            self.pos.len().checked_sub(2).map(|i| self.pos[i]),
+            self.pos_string.chars().last(),
+        ) else {
+            bail!("Reference outside of a word");
+        };
+        self.nodes[p].children.insert(c, idx);
+        Ok(())
     }
 
     /// Process a single character and update state.
-    fn process_char(&mut self, c: char, header_base: u32, state: &mut ParseState) {
-        dbg!("start", c, &state, &self.pos_string);
+    fn process_char(
+        &mut self,
+        c: char,
+        header_base: u32,
+        state: &mut ParseState,
+    ) -> anyhow::Result<()> {
+        if self.trace {
+            dbg!("start", c, &state, &self.pos_string);
+        }
         match state {
             ParseState::Escape => {
-                self.add_char(c);
+                self.add_char(c)?;
                 *state = ParseState::InWord;
             }
             ParseState::Remove => {
-                let count = if c.is_numeric() {
-                    let out = c.to_digit(10).unwrap();
-                    // As per the spec, out can't be 1
-                    assert_ne!(out, 1);
-                    out - 1
-                } else {
-                    1
+                let count = match c.to_digit(10) {
+                    // As per the spec, the count can't be 1
+                    Some(0 | 1) => bail!("Invalid removal count: {c}"),
+                    Some(out) => out - 1,
+                    None => 1,
                 };
                 for _ in 0..count {
-                    if self.pos.pop().is_none() {
-                        self.dbg_state();
-                        unreachable!("No more nodes to pop");
-                    } else {
-                        self.pos_string.pop();
-                    }
-                    if self.pos.is_empty() {
-                        self.dbg_state();
-                        unreachable!("No more nodes in path");
+                    if self.pos.len() <= 1 {
+                        bail!("Removed past the start of a word");
                     }
+                    self.pos.pop();
+                    self.pos_string.pop();
                 }
                 if !c.is_numeric() {
                     match c {
                         '\\' => *state = ParseState::Escape,
                         '$' => {
-                            if let Some(cur) = self.pos.last() {
-                                cur.borrow_mut().eow = true;
-                            }
+                            self.mark_eow();
                             *state = ParseState::Remove;
                         }
                         '<' => {
@@ -189,7 +193,7 @@ impl TrieBuilder {
                         }
                         other => {
                             *state = ParseState::InWord;
-                            self.process_char(other, header_base, state);
+                            self.process_char(other, header_base, state)?;
                         }
                     }
                 }
@@ -198,13 +202,9 @@ impl TrieBuilder {
                 if c == ';' {
                     let number_str: String = chars.iter().collect();
                     let idx = u32::from_str_radix(&number_str[1..], header_base)
-                        .expect("Failed to convert number") as usize;
-                    if idx < self.nodes.len() {
-                        self.jump_to(idx + 1);
-                    } else {
-                        self.dbg_state();
-                        panic!("Index out of bounds: {idx}");
-                    }
+                        .context(format!("Invalid reference: {number_str}"))?
+                        as usize;
+                    self.jump_to(idx + 1)?;
                     *state = ParseState::InWord;
                 } else {
                     chars.push(c);
@@ -213,40 +213,45 @@ impl TrieBuilder {
             ParseState::InWord => match c {
                 '\\' => *state = ParseState::Escape,
                 '$' => {
-                    if let Some(cur) = self.pos.last() {
-                        cur.borrow_mut().eow = true;
-                    }
+                    self.mark_eow();
                     *state = ParseState::Remove;
                 }
                 '<' => *state = ParseState::Remove,
                 '#' => {
                     *state = ParseState::AbsoluteReference { chars: vec![c] };
                 }
-                _ => self.add_char(c),
+                _ => self.add_char(c)?,
             },
         }
-        self.dbg_state();
-        dbg!("end", c, &state, &self.pos_string);
+        if self.trace {
+            self.dbg_state();
+            dbg!("end", c, &state, &self.pos_string);
+        }
+        Ok(())
+    }
+
+    /// Mark the last node in the current path as the end of a word.
+    fn mark_eow(&mut self) {
+        if let Some(&cur) = self.pos.last() {
+            self.nodes[cur].eow = true;
+        }
     }
 
     /// Add a character as a child node to the last node in the current path.
-    fn add_char(&mut self, c: char) {
-        if let Some(parent) = self.pos.last().cloned() {
-            let mut parent_borrow = parent.borrow_mut();
-            if let Some(child) = parent_borrow.children.get(&c) {
-                self.pos.push(child.clone());
-                self.pos_string.push(c);
-            } else {
-                // TODO: causes leak
-                let new_node = Rc::new(RefCell::new(TrieNode::new(c, false)));
-                parent_borrow.children.insert(c, new_node.clone());
-                self.nodes.push(new_node.clone());
-                self.pos.push(new_node);
-            }
+    fn add_char(&mut self, c: char) -> anyhow::Result<()> {
+        let Some(&parent) = self.pos.last() else {
+            bail!("No node to add `{c}` to");
+        };
+        if let Some(&child) = self.nodes[parent].children.get(&c) {
+            self.pos.push(child);
+            self.pos_string.push(c);
         } else {
-            self.dbg_state();
-            unreachable!();
+            let new_node = self.nodes.len();
+            self.nodes.push(TrieNode::new(c, false));
+            self.nodes[parent].children.insert(c, new_node);
+            self.pos.push(new_node);
         }
+        Ok(())
     }
 }
 
@@ -268,50 +273,46 @@ impl TrieNode {
 }
 
 /// Recursively convert the builder trie into the output Trie structure.
-fn convert_trie(builder_root: Rc<RefCell<TrieNode>>) -> Trie {
+fn convert_trie(nodes: &[TrieNode]) -> anyhow::Result<Trie> {
     const MAX_DEPTH: usize = 1024;
-    fn rec_convert(node: &Rc<RefCell<TrieNode>>, current: &mut String, builder: &mut MapBuilder<Vec<u8>>, depth: &mut usize) {
-        assert!(*depth < MAX_DEPTH, "Max depth exceeded, recursion limit reached");
-        // let node_ref = node.borrow();
-        // let mut out = if node_ref.eow {
-        //     crate::trie::TrieNode::some_default()
-        // } else {
-        //     crate::trie::TrieNode::none()
-        // };
-        // for (ch, child) in &node_ref.children {
-        //     out.children.insert(*ch, rec_convert(child));
-        // }
-        // out
-        let node_ref = node.borrow();
+    fn rec_convert(
+        nodes: &[TrieNode],
+        node: usize,
+        current: &mut String,
+        builder: &mut MapBuilder<Vec<u8>>,
+        depth: &mut usize,
+    ) -> anyhow::Result<()> {
+        if *depth >= MAX_DEPTH {
+            bail!("Max depth exceeded, recursion limit reached");
+        }
+        let node_ref = &nodes[node];
         if node_ref.eow {
-            builder.insert(current.as_bytes(), 0).unwrap();
+            builder.insert(current.as_bytes(), 0)?;
         }
-        let mut sorted_children: Vec<_> = node_ref
-            .children
-            .iter()
-            .collect();
+        let mut sorted_children: Vec<_> = node_ref.children.iter().collect();
         sorted_children.sort_by(|a, b| a.0.cmp(b.0));
-        for (&ch, child) in sorted_children {
+        for (&ch, &child) in sorted_children {
             current.push(ch);
             *depth += 1;
-            rec_convert(child, current, builder, depth);
+            rec_convert(nodes, child, current, builder, depth)?;
             current.pop();
             *depth -= 1;
         }
+        Ok(())
     }
     let mut builder = fst::map::MapBuilder::memory();
     let mut current = String::new();
     let mut depth = 0;
-    rec_convert(&builder_root, &mut current, &mut builder, &mut depth);
+    rec_convert(nodes, 0, &mut current, &mut builder, &mut depth)?;
     let root_converted = builder.into_map();
-    Trie {
+    Ok(Trie {
         root: root_converted,
         options: TrieOptions::default(),
-    }
+    })
 }
 
 /// Refactored `parse_body` function.
-pub fn parse_body(input: &[String], header: &Header) -> Trie {
+pub fn parse_body(input: &[String], header: &Header) -> anyhow::Result<Trie> {
     let mut builder = TrieBuilder::new();
     let mut state = ParseState::InWord;
     let header_base = header.base as u32;
@@ -321,31 +322,164 @@ pub fn parse_body(input: &[String], header: &Header) -> Trie {
             if ch == '\n' {
                 continue;
             }
-            builder.process_char(ch, header_base, &mut state);
+            builder.process_char(ch, header_base, &mut state)?;
         }
     }
-    let root = builder.nodes.first().unwrap().clone();
-    convert_trie(root)
+    convert_trie(&builder.nodes)
+}
+
+/// Parse a `TrieXv3` body, which uses absolute references only.
+fn parse_body_v3(input: &[String], header: &Header) -> anyhow::Result<Trie> {
+    parse_body(input, header)
+}
+
+/// Parse a `TrieXv4` body.
+// TODO: Relative references
+fn parse_body_v4(input: &[String], header: &Header) -> anyhow::Result<Trie> {
+    parse_body(input, header)
 }
 
 pub fn parse_trie(input: &[String]) -> anyhow::Result<(Header, Trie)> {
     let (counter, header) = parse_header(input)?;
     let body = &input[counter..];
-    let trie = parse_body(body, &header);
+    let trie = match header.version.to_u8()? {
+        3 => parse_body_v3(body, &header)?,
+        4 => parse_body_v4(body, &header)?,
+        version => bail!("Unsupported cspell trie version: {version}"),
+    };
     Ok((header, trie))
 }
 
+/// Width at which the body of a written trie is wrapped.
+const LINE_WIDTH: usize = 80;
+
+/// Node of the character tree a trie is written from.
+#[derive(Default)]
+struct WriteNode {
+    eow: bool,
+    children: BTreeMap<char, WriteNode>,
+}
+
+/// Writes a trie body, tracking the removals owed before the next token.
+struct BodyWriter {
+    body: String,
+    /// Characters to remove from the current path before the next token
+    pops: usize,
+    /// Whether the next token removes a character by itself, as after `$`
+    in_remove: bool,
+}
+
+impl BodyWriter {
+    /// Emit the removals owed before the next token, leaving the last one
+    /// to that token if the writer is removing.
+    fn flush(&mut self) {
+        let mut pops = self.pops;
+        if !self.in_remove && pops > 0 {
+            self.body.push('<');
+            self.in_remove = true;
+        }
+        if self.in_remove {
+            // The next token removes the last character
+            pops = pops.saturating_sub(1);
+        }
+        while pops > 0 {
+            // A digit `n` removes `n - 1` characters
+            let count = pops.min(8);
+            self.body.push(char::from_digit(count as u32 + 1, 10).unwrap());
+            pops -= count;
+        }
+        self.pops = 0;
+    }
+
+    fn push_char(&mut self, c: char) {
+        self.flush();
+        if matches!(c, '$' | '<' | '\\' | '#' | ';') || c.is_ascii_digit() {
+            self.body.push('\\');
+        }
+        self.body.push(c);
+        self.in_remove = false;
+    }
+
+    fn push_eow(&mut self) {
+        self.flush();
+        self.body.push('$');
+        self.in_remove = true;
+    }
+
+    /// Write the words under `node`, marking the end of a word once the
+    /// words it prefixes are written.
+    fn write(&mut self, node: &WriteNode) {
+        for (&c, child) in &node.children {
+            self.push_char(c);
+            self.write(child);
+            self.pops += 1;
+        }
+        if node.eow {
+            self.push_eow();
+        }
+    }
+}
+
+/// Serialize the allowed words of a trie in the cspell `TrieXv3` format,
+/// without references.
+pub fn write_v3(trie: &Trie) -> Vec<String> {
+    let mut root = WriteNode::default();
+    for rule in trie.to_rules() {
+        let (Rule::Allow(word) | Rule::AllowWithFrequency(word, _)) = rule else {
+            continue;
+        };
+        if word.is_empty() || word.contains('\n') {
+            continue;
+        }
+        let node = word
+            .chars()
+            .fold(&mut root, |node, c| node.children.entry(c).or_default());
+        node.eow = true;
+    }
+    let mut writer = BodyWriter {
+        body: String::new(),
+        pops: 0,
+        in_remove: false,
+    };
+    writer.write(&root);
+    let mut lines = vec![
+        "TrieXv3".to_string(),
+        "base=10".to_string(),
+        "__DATA__".to_string(),
+    ];
+    let chars = writer.body.chars().collect::<Vec<_>>();
+    lines.extend(
+        chars
+            .chunks(LINE_WIDTH)
+            .map(|chunk| chunk.iter().collect::<String>()),
+    );
+    lines
+}
+
+/// Whether `path` is a cspell trie, possibly compressed (`.trie`, `.trie.gz`
+/// or `.trie.zst`).
+pub fn is_trie_file<P: AsRef<std::path::Path>>(path: P) -> bool {
+    let filename = path.as_ref().to_string_lossy();
+    [".trie", ".trie.gz", ".trie.zst"]
+        .iter()
+        .any(|extension| filename.ends_with(extension))
+}
+
 pub fn file_to_lines<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Vec<String>> {
     // Read the entire file into a byte buffer
     let buf = std::fs::read(&path)?;
     let filename = path.as_ref().to_string_lossy();
 
-    // Decode if gzipped, otherwise assume UTF-8 text
+    // Decode if compressed, otherwise assume UTF-8 text
     let text = if filename.ends_with(".gz") {
         let mut decoder = GzDecoder::new(&buf[..]);
         let mut s = String::new();
         decoder.read_to_string(&mut s)?;
         s
+    } else if filename.ends_with(".zst") {
+        let decoded = zstd::decode_all(&buf[..])?;
+        String::from_utf8(decoded)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
     } else {
         String::from_utf8(buf)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
@@ -359,6 +493,79 @@ pub fn file_to_lines<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Vec<
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_zstd_file_to_lines() {
+        let dir = std::env::temp_dir().join(format!("csc-zstd-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt.zst");
+        let compressed = zstd::encode_all("hello\nworld\r\nfoo\n".as_bytes(), 0).unwrap();
+        std::fs::write(&path, compressed).unwrap();
+        let lines = file_to_lines(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(lines, vec!["hello", "world", "foo"]);
+    }
+
+    #[test]
+    fn test_write_v3_round_trip() {
+        let input = ["TrieXv3", "base=10", "__DATA__", "a$word$<3no$"]
+            .map(ToString::to_string)
+            .to_vec();
+        let (_, trie) = parse_trie(&input).unwrap();
+        let written = write_v3(&trie);
+        assert_eq!(written[..3], ["TrieXv3", "base=10", "__DATA__"]);
+        let (header, round_trip) = parse_trie(&written).unwrap();
+        assert_eq!(header.version.to_u8().unwrap(), 3);
+        assert_eq!(round_trip.to_vec(), trie.to_vec());
+    }
+
+    #[test]
+    fn test_write_v3_prefixes_and_escapes() {
+        let words = [
+            "a", "ab", "abc", "abd", "b", "0th", "1st", "a$b", "x<y", "long", "longer",
+            "internationalization", "internet", "j",
+        ]
+        .map(ToString::to_string);
+        let trie = crate::Dictionary::new_from_strings(&words).compile().unwrap();
+        let (_, round_trip) = parse_trie(&write_v3(&trie)).unwrap();
+        assert_eq!(round_trip.to_vec(), trie.to_vec());
+    }
+
+    /// Resident set size of the process in bytes, from `/proc/self/statm`.
+    #[cfg(target_os = "linux")]
+    fn rss() -> usize {
+        let statm = std::fs::read_to_string("/proc/self/statm").unwrap();
+        let pages: usize = statm.split_whitespace().nth(1).unwrap().parse().unwrap();
+        pages * 4096
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_parse_frees_nodes() {
+        let words = (0..2000).map(|i| format!("ax{i}word")).collect::<Vec<_>>();
+        let trie = crate::Dictionary::new_from_strings(&words).compile().unwrap();
+        let mut input = write_v3(&trie);
+        // A reference from `a` back to itself makes every word cyclic
+        input[3].insert_str(0, "ab$<ab#0;$2");
+        for _ in 0..10 {
+            assert!(parse_trie(&input).is_err());
+        }
+        let before = rss();
+        for _ in 0..200 {
+            assert!(parse_trie(&input).is_err());
+        }
+        let growth = rss().saturating_sub(before);
+        assert!(growth < 32 * 1024 * 1024, "RSS grew by {growth} bytes");
+    }
+
+    #[test]
+    fn test_is_trie_file() {
+        assert!(is_trie_file("en_US.trie"));
+        assert!(is_trie_file("en_US.trie.gz"));
+        assert!(is_trie_file("en_US.trie.zst"));
+        assert!(!is_trie_file("en_US.txt"));
+        assert!(!is_trie_file("en_US.txt.gz"));
+    }
+
     #[test]
     fn test_parse_header() {
         let input = vec![
@@ -368,10 +575,33 @@ mod tests {
         ];
         let (counter, header) = parse_header(&input).unwrap();
         assert_eq!(counter, 3);
-        assert_eq!(header.version.to_u8(), 4);
+        assert_eq!(header.version.to_u8().unwrap(), 4);
         assert_eq!(header.base, 10);
     }
 
+    #[test]
+    fn test_parse_unsupported_version() {
+        let input = ["TrieXv9", "base=10", "__DATA__", "a$"]
+            .map(ToString::to_string)
+            .to_vec();
+        let error = parse_trie(&input).unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported cspell trie version: 9");
+        let input = ["base=10", "__DATA__", "a$"].map(ToString::to_string).to_vec();
+        assert!(parse_trie(&input).is_err());
+    }
+
+    #[test]
+    fn test_parse_malformed_body() {
+        let header = Header {
+            version: Version("TrieXv3".to_string()),
+            base: 10,
+        };
+        for body in ["ab<1c$", "a$<<<b$", "ab$#99;", "$#x;"] {
+            let input = vec![body.to_string()];
+            assert!(parse_body(&input, &header).is_err(), "{body}");
+        }
+    }
+
     #[test]
     fn test_parse_body_word_end() {
         let header = Header {
@@ -379,7 +609,7 @@ mod tests {
             base: 10,
         };
         let input = vec!["a$".to_string(), "b$".to_string(), "c$".to_string()];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         assert!(trie.contains("a"));
         assert!(trie.contains("b"));
         assert!(trie.contains("c"));
@@ -400,7 +630,7 @@ mod tests {
             "c$".to_string(),
             "<2def$".to_string(),
         ];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         assert!(!trie.contains("a"));
         assert!(trie.contains("a$b"));
         assert!(trie.contains("a$c"));
@@ -414,7 +644,7 @@ mod tests {
             base: 32,
         };
         let input = vec!["a$word$<3no$".to_string()];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         let mut v = trie.to_vec();
         v.sort();
         assert_eq!(v, vec!["a", "no", "word"]);
@@ -427,7 +657,7 @@ mod tests {
             base: 32,
         };
         let input = vec!["apple$<<<n$<banb#1;".to_string()];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         let mut v = trie.to_vec();
         v.sort();
         assert_eq!(v, vec!["an", "apple", "banbn", "banbpple"]);
@@ -440,7 +670,7 @@ mod tests {
             base: 32,
         };
         let input = vec![r"\'cause$5sup$3tis$2wa#9;".to_string()];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         let mut v = trie.to_vec();
         v.sort();
         assert_eq!(v, vec!["'cause", "'sup", "'tis", "'twas"]);
@@ -453,7 +683,7 @@ mod tests {
             base: 32,
         };
         let input = vec![r"\'cause$5sup$3tis$2wa#9;<4\0th$2$\1st$2$\2nd$2$\3r#g;".to_string()];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         let mut v = trie.to_vec();
         v.sort();
         assert_eq!(
@@ -471,7 +701,7 @@ mod tests {
             base: 32,
         };
         let input = vec!["c$a#0;".to_string()];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         let mut v = trie.to_vec();
         v.sort();
         assert_eq!(v, vec!["a", "c"]);
@@ -484,7 +714,7 @@ mod tests {
             base: 32,
         };
         let input = vec!["ab$c#0;$".to_string()];
-        let trie = parse_body(&input, &header);
+        let trie = parse_body(&input, &header).unwrap();
         let mut v = trie.to_vec();
         v.sort();
         assert_eq!(v, vec!["ab", "ac"]);
@@ -500,7 +730,7 @@ mod tests {
         for word in &v {
             println!("{}", word);
         }
-        assert_eq!(header.version.to_u8(), 3);
+        assert_eq!(header.version.to_u8().unwrap(), 3);
         assert!(v.contains(&"'cause".to_string()));
     }
 
@@ -511,7 +741,7 @@ mod tests {
         let lines = file_to_lines(path).unwrap();
         let (header, trie) = parse_trie(&lines).unwrap();
         dbg!(&trie.to_vec());
-        assert_eq!(header.version.to_u8(), 3);
+        assert_eq!(header.version.to_u8().unwrap(), 3);
         assert!(trie.contains("'cause'"))
     }
 }
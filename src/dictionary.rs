@@ -1,21 +1,49 @@
-use std::{io::BufRead, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::BufRead,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime},
+};
 
 use ahash::HashMapExt;
 use anyhow::{Context, bail};
 use serde::{Deserialize, Serialize};
 
-use crate::{HashMap, Trie, filesystem, store_path};
+use crate::{
+    HashMap, Trie, filesystem,
+    settings::{CustomDictionaryDefinitionType, GitOptions},
+    store_path,
+};
+
+/// Cleared by `--no-cache`
+static CACHE: AtomicBool = AtomicBool::new(true);
+
+/// Enable or disable reading and writing compiled dictionaries and file
+/// results in the cache, regardless of each dictionary's own setting.
+pub fn set_cache_enabled(enabled: bool) {
+    CACHE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn cache_enabled() -> bool {
+    CACHE.load(Ordering::Relaxed)
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Command {
     CaseSensitive,
     Cache(bool),
+    /// Expand the `word/suffix,suffix` entries after it into each suffixed word
+    Affixes,
 }
 
 impl Command {
     pub fn from_str(s: &str) -> Option<Self> {
         if s == "case-sensitive" {
             Some(Self::CaseSensitive)
+        } else if s == "affixes" {
+            Some(Self::Affixes)
         } else if s.starts_with("cache:") {
             let value = s.trim_start_matches("cache:");
             if value == "true" {
@@ -35,6 +63,12 @@ impl Command {
 pub enum Rule {
     /// A rule that allows a word
     Allow(String),
+    /// A rule that allows a word, with how frequently it's used, written as
+    /// `word<TAB>frequency`
+    AllowWithFrequency(String, u64),
+    /// A rule that allows a word and the words formed by adding each of the
+    /// suffixes, written as `word/suffix,suffix` after the `affixes` command
+    AllowWithAffixes(String, Vec<String>),
     /// A rule that disallows a word
     Disallow(String),
     /// A command rule
@@ -43,10 +77,30 @@ pub enum Rule {
     Comment(String),
 }
 
-fn load_dictionary_line(line: &str) -> anyhow::Result<Rule> {
-    // let trimmed = line.trim();
-    // TODO: Special for cspell
-    let trimmed = line.split("/").next().unwrap_or(line).trim();
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Allow(word) => write!(f, "{word}"),
+            Self::AllowWithFrequency(word, frequency) => write!(f, "{word}\t{frequency}"),
+            Self::AllowWithAffixes(word, suffixes) => write!(f, "{word}/{}", suffixes.join(",")),
+            Self::Disallow(word) => write!(f, "!{word}"),
+            Self::Command(command) => write!(f, "# csc: {command:?}"),
+            Self::Comment(comment) => write!(f, "# {comment}"),
+        }
+    }
+}
+
+/// Parse a line of a wordlist. With `affixes`, set once the wordlist has had
+/// the `affixes` command, entries are `word/suffix,suffix`; otherwise `/` is
+/// part of the word, as in `tcp/ip`.
+fn load_dictionary_line(line: &str, affixes: bool) -> anyhow::Result<Rule> {
+    let line = line.trim();
+    // Comments may contain `/`
+    let is_comment = line.starts_with('#') || line.starts_with("//");
+    let (trimmed, affixes) = match line.split_once('/') {
+        Some((word, suffixes)) if affixes && !is_comment => (word.trim(), Some(suffixes)),
+        _ => (line, None),
+    };
     if trimmed.is_empty() {
         return Ok(Rule::Comment("".to_string())); // Empty lines are ignored
     }
@@ -68,37 +122,196 @@ fn load_dictionary_line(line: &str) -> anyhow::Result<Rule> {
         }
         // TODO: Handle case sensitivity
     } else if trimmed.starts_with("!") {
-        let disallow = trimmed.trim_start_matches('!').trim().to_ascii_lowercase();
+        let disallow = trimmed.trim_start_matches('!').trim().to_lowercase();
         Rule::Disallow(disallow)
     } else if trimmed.starts_with("+") {
-        let allow = trimmed.trim_start_matches('+').trim().to_ascii_lowercase();
+        let allow = trimmed.trim_start_matches('+').trim().to_lowercase();
         Rule::Allow(allow)
+    } else if let Some((word, frequency)) = trimmed.rsplit_once('\t')
+        && let Ok(frequency) = frequency.trim().parse()
+    {
+        Rule::AllowWithFrequency(word.trim().to_lowercase(), frequency)
+    } else if let Some(affixes) = affixes {
+        let suffixes = affixes
+            .split(',')
+            .map(|suffix| suffix.trim().to_lowercase())
+            .filter(|suffix| !suffix.is_empty())
+            .collect();
+        Rule::AllowWithAffixes(trimmed.to_lowercase(), suffixes)
     } else {
-        Rule::Allow(trimmed.to_ascii_lowercase().to_string())
+        Rule::Allow(trimmed.to_lowercase())
     })
 }
 
-fn load_dictionary_format(s: &str) -> anyhow::Result<Vec<Rule>> {
-    s.lines()
-        .map(load_dictionary_line)
-        .collect::<Result<Vec<_>, _>>()
+/// Parse the lines of a wordlist, with `word/suffix` entries after an
+/// `affixes` command.
+fn load_dictionary_lines<S: AsRef<str>>(
+    lines: impl IntoIterator<Item = anyhow::Result<S>>,
+) -> anyhow::Result<Vec<Rule>> {
+    let mut affixes = false;
+    let mut rules = Vec::new();
+    for line in lines {
+        let rule = load_dictionary_line(line?.as_ref(), affixes)?;
+        affixes |= matches!(rule, Rule::Command(Command::Affixes));
+        rules.push(rule);
+    }
+    Ok(rules)
 }
 
-fn load_dictionary_format_from_file<P: AsRef<std::path::Path>>(p: P) -> anyhow::Result<Vec<Rule>> {
+pub fn load_dictionary_format_from_file<P: AsRef<std::path::Path>>(
+    p: P,
+) -> anyhow::Result<Vec<Rule>> {
+    filesystem::ensure_not_lfs_pointer(&p)?;
     let file = std::fs::File::open(p)?;
     // stream lines for memory efficiency
     let reader = std::io::BufReader::new(file);
-    let mut rules = Vec::new();
-    for line in reader.lines() {
-        let line = line?;
-        let rule = load_dictionary_line(&line)?;
-        rules.push(rule);
+    load_dictionary_lines(reader.lines().map(|line| line.map_err(anyhow::Error::from)))
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+/// Add `suffix` to `word` with the common English spelling changes: a silent
+/// `e` is dropped (`make` -> `making`) and the final consonant of a
+/// one-syllable word ending in consonant-vowel-consonant is doubled (`run` ->
+/// `running`), both only before a suffix starting with a vowel.
+fn add_suffix(word: &str, suffix: &str) -> String {
+    let chars = word.chars().collect::<Vec<_>>();
+    if !suffix.starts_with(is_vowel) {
+        return format!("{word}{suffix}");
     }
-    Ok(rules)
+    if let [.., before, 'e'] = chars[..]
+        && before != 'e'
+    {
+        return format!("{}{suffix}", &word[..word.len() - 1]);
+    }
+    let syllables = chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| is_vowel(c) && (i == 0 || !is_vowel(chars[i - 1])))
+        .count();
+    if syllables == 1
+        && let [.., first, vowel, last] = chars[..]
+        && !is_vowel(first)
+        && is_vowel(vowel)
+        && !is_vowel(last)
+        && !matches!(last, 'w' | 'x' | 'y')
+    {
+        return format!("{word}{last}{suffix}");
+    }
+    format!("{word}{suffix}")
+}
+
+/// Expand `word/suffix` rules into an allow rule for each word.
+pub fn expand_affixes(rules: &[Rule]) -> Vec<Rule> {
+    let mut expanded = Vec::with_capacity(rules.len());
+    for rule in rules {
+        match rule {
+            Rule::AllowWithAffixes(word, suffixes) => {
+                expanded.push(Rule::Allow(word.clone()));
+                expanded.extend(
+                    suffixes
+                        .iter()
+                        .map(|suffix| Rule::Allow(add_suffix(word, suffix))),
+                );
+            }
+            rule => expanded.push(rule.clone()),
+        }
+    }
+    expanded
+}
+
+/// Merge rules so that every word is allowed or disallowed at most once, with
+/// later rules taking precedence over earlier ones for the same word.
+pub fn merge_rules<I: IntoIterator<Item = Rule>>(rules: I) -> Vec<Rule> {
+    let mut merged: Vec<Rule> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for rule in rules {
+        let word = match &rule {
+            Rule::Allow(word)
+            | Rule::AllowWithFrequency(word, _)
+            | Rule::AllowWithAffixes(word, _)
+            | Rule::Disallow(word) => word.clone(),
+            Rule::Command(_) => {
+                merged.push(rule);
+                continue;
+            }
+            Rule::Comment(_) => continue,
+        };
+        if let Some(&position) = positions.get(&word) {
+            merged[position] = rule;
+        } else {
+            positions.insert(word, merged.len());
+            merged.push(rule);
+        }
+    }
+    merged
+}
+
+/// A difference between the allowed/disallowed words of two rule sets
+#[derive(Debug, Clone)]
+pub enum RuleChange {
+    Added(Rule),
+    Removed(Rule),
+    Changed { old: Rule, new: Rule },
+}
+
+/// Compare the allow/disallow rules of two rule sets, sorted by word.
+pub fn diff_rules(old: &[Rule], new: &[Rule]) -> Vec<RuleChange> {
+    fn words(rules: &[Rule]) -> BTreeMap<&str, &Rule> {
+        rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Allow(word)
+                | Rule::AllowWithFrequency(word, _)
+                | Rule::AllowWithAffixes(word, _)
+                | Rule::Disallow(word) => Some((word.as_str(), rule)),
+                Rule::Command(_) | Rule::Comment(_) => None,
+            })
+            .collect()
+    }
+
+    let old = words(old);
+    let new = words(new);
+    let mut all_words = old.keys().chain(new.keys()).collect::<Vec<_>>();
+    all_words.sort();
+    all_words.dedup();
+    all_words
+        .into_iter()
+        .filter_map(|word| match (old.get(word), new.get(word)) {
+            (None, Some(&new)) => Some(RuleChange::Added(new.clone())),
+            (Some(&old), None) => Some(RuleChange::Removed(old.clone())),
+            (Some(&old), Some(&new))
+                if matches!(old, Rule::Disallow(_)) != matches!(new, Rule::Disallow(_)) =>
+            {
+                Some(RuleChange::Changed {
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// A compiled dictionary in the cache.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Hash of the source the dictionary was compiled from
+    pub hash: String,
+    /// Absolute path of that source, so the entry can be pruned once it's
+    /// gone
+    pub source: PathBuf,
 }
 
+/// Compiled `.bin` files that no entry of the store knows are only pruned
+/// once they're this old, as a dictionary being saved writes its file
+/// before its entry.
+const UNTRACKED_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Default, Debug, Serialize, Deserialize)]
-pub struct DictCacheStore(pub HashMap<String, String>);
+pub struct DictCacheStore(pub HashMap<String, CacheEntry>);
 
 impl DictCacheStore {
     pub fn new() -> Self {
@@ -118,13 +331,93 @@ impl DictCacheStore {
 
     pub fn dump_to_file<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
         let data = serde_json::to_vec(self).expect("Failed to serialize TrieHashStore");
-        std::fs::write(path, data)
+        filesystem::write_atomic(path, &data)
+    }
+
+    /// Load the store at `path`, apply `f` and write it back, without losing
+    /// the changes of other threads or processes updating it at the same
+    /// time. The lock file is next to it.
+    pub fn update_at(path: &Path, f: impl FnOnce(&mut Self)) -> anyhow::Result<()> {
+        filesystem::with_lock(path, || {
+            let mut store = Self::load_from_file(path)?;
+            f(&mut store);
+            store.dump_to_file(path)?;
+            Ok(())
+        })
+    }
+
+    /// Remove the entries of the store in `cache_dir` whose source is gone,
+    /// with their compiled files, and compiled files that no entry knows,
+    /// like those of an older cache format. Returns how many were removed.
+    /// Files that can't be read or removed are skipped with a warning.
+    pub fn prune_at(cache_dir: &Path) -> anyhow::Result<usize> {
+        let mut removed = Vec::new();
+        let mut known = Vec::new();
+        Self::update_at(&cache_dir.join(CACHE_STORE_NAME), |store| {
+            store.0.retain(|key, entry| {
+                let keep = entry.source.exists();
+                if !keep {
+                    removed.push(key.clone());
+                }
+                keep
+            });
+            known = store.0.keys().cloned().collect();
+        })?;
+        let mut count = removed.len();
+        for key in removed {
+            let path = cache_dir.join(format!("{key}.bin"));
+            if let Err(e) = std::fs::remove_file(&path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                eprintln!("Failed to remove cache file {}: {e}", path.display());
+            }
+        }
+        let entries = std::fs::read_dir(cache_dir)
+            .context(format!("Failed to read cache: {}", cache_dir.display()))?;
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!("Skipping unreadable cache entry: {e}");
+                    continue;
+                }
+            };
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "bin")
+                || path
+                    .file_stem()
+                    .is_some_and(|stem| known.iter().any(|key| stem == key.as_str()))
+            {
+                continue;
+            }
+            let age = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| {
+                    SystemTime::now()
+                        .duration_since(modified)
+                        .unwrap_or_default()
+                });
+            match age {
+                Ok(age) if age < UNTRACKED_MAX_AGE => {}
+                Ok(_) => match std::fs::remove_file(&path) {
+                    Ok(()) => count += 1,
+                    Err(e) => eprintln!("Failed to remove cache file {}: {e}", path.display()),
+                },
+                Err(e) => eprintln!("Skipping cache file {}: {e}", path.display()),
+            }
+        }
+        Ok(count)
     }
 }
 
+/// The file in the cache that records the source hash of each compiled
+/// dictionary.
+const CACHE_STORE_NAME: &str = "cache.json";
+
 pub fn dict_cache_store_location() -> anyhow::Result<PathBuf> {
     let mut path = crate::cache_path();
-    path.push("cache.json");
+    path.push(CACHE_STORE_NAME);
     Ok(path)
 }
 
@@ -197,55 +490,148 @@ impl Dictionary {
     }
 
     pub fn new_from_strings(strings: &[String]) -> Self {
-        let rules = strings
-            .iter()
-            .map(|s| load_dictionary_line(s))
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
-        Self::Rules(rules)
+        Self::Rules(Self::rules_from_strings(strings))
+    }
+
+    pub fn rules_from_strings(strings: &[String]) -> Vec<Rule> {
+        load_dictionary_lines(strings.iter().map(Ok)).unwrap()
+    }
+
+    /// Resolve an entry of a directory dictionary's `paths`, which may be
+    /// absolute or relative to the directory.
+    fn resolve_config_path(dir: &Path, path_str: &str) -> PathBuf {
+        let path_str = path_str.trim();
+        if Path::new(path_str).is_absolute() {
+            PathBuf::from(path_str)
+        } else {
+            relative_path::RelativePath::new(path_str).to_path(dir)
+        }
+    }
+
+    /// Hash everything the compiled dictionary at `path` was built from, so a
+    /// change to any source invalidates the cache.
+    ///
+    /// For directories this includes every resolved `paths` entry, since those
+    /// can point outside the directory.
+    fn source_hash(&self, path: &PathBuf) -> anyhow::Result<String> {
+        let Self::Directory(dir) = self else {
+            return filesystem::get_path_hash(path);
+        };
+        let config_path = dir.join("csc-config.json");
+        let content: DictionaryConfig =
+            serde_hjson::from_reader(std::fs::File::open(config_path)?)?;
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(filesystem::get_path_hash(dir)?.as_bytes());
+        for path_str in &content.paths {
+            let file_path = Self::resolve_config_path(dir, path_str);
+            if file_path.exists() {
+                hasher.update(filesystem::get_path_hash(&file_path)?.as_bytes());
+            }
+        }
+        Ok(hasher.finalize().to_hex().to_string())
     }
 
-    fn load_from_cache_inner(&self, path: &PathBuf) -> anyhow::Result<Option<Trie>> {
-        let path_hash = blake3::hash(path.to_str().unwrap().as_bytes())
+    /// The key of a compiled dictionary in the cache, derived from its path
+    fn cache_key(path: &Path) -> String {
+        blake3::hash(path.to_str().unwrap().as_bytes())
             .to_hex()
-            .to_string();
-        let fs_hash = filesystem::get_path_hash(path)?;
-        let cache_hash_store = DictCacheStore::load_from_file(dict_cache_store_location()?)?;
-        if let Some(hash) = cache_hash_store.0.get(&path_hash) {
-            if hash == &fs_hash {
-                let cache_path = filesystem::cache_path().join(format!("{path_hash}.bin"));
-                if cache_path.exists() {
-                    let trie = Trie::load_from_file(cache_path)?;
-                    return Ok(Some(trie));
-                }
+            .to_string()
+    }
+
+    /// The cache key of this dictionary, if it is cached
+    pub fn get_cache_key(&self) -> Option<String> {
+        match self {
+            Self::File(path) | Self::Directory(path) | Self::Trie(path) => {
+                Some(Self::cache_key(path))
+            }
+            Self::Custom { definition, root } => {
+                Some(Self::cache_key(&root.join(definition.path())))
+            }
+            Self::Rules(_) => None,
+        }
+    }
+
+    fn load_from_cache_inner(
+        &self,
+        path: &PathBuf,
+        cache_dir: &Path,
+    ) -> anyhow::Result<Option<Trie>> {
+        let path_hash = Self::cache_key(path);
+        let fs_hash = self.source_hash(path)?;
+        let cache_hash_store = DictCacheStore::load_from_file(cache_dir.join(CACHE_STORE_NAME))?;
+        if let Some(entry) = cache_hash_store.0.get(&path_hash)
+            && entry.hash == fs_hash
+        {
+            let cache_path = cache_dir.join(format!("{path_hash}.bin"));
+            if cache_path.exists() {
+                let trie = Trie::load_from_file(cache_path)?;
+                return Ok(Some(trie));
             }
         }
         Ok(None)
     }
 
-    pub fn load_from_cache(&self, path: &PathBuf) -> anyhow::Result<Option<Trie>> {
-        self.load_from_cache_inner(path)
+    /// The compiled trie of the dictionary at `path` from `cache_dir`, if
+    /// it's there and up to date. Nothing is cached without a `cache_dir`.
+    pub fn load_from_cache(
+        &self,
+        path: &PathBuf,
+        cache_dir: Option<&Path>,
+    ) -> anyhow::Result<Option<Trie>> {
+        let Some(cache_dir) = cache_dir else {
+            return Ok(None);
+        };
+        self.load_from_cache_inner(path, cache_dir)
             .context(format!("Failed to load cache for {}", path.display()))
     }
 
-    fn save_to_cache_inner(trie: &Trie, path: &PathBuf) -> anyhow::Result<()> {
-        let path_hash = blake3::hash(path.to_str().unwrap().as_bytes())
-            .to_hex()
-            .to_string();
-        let fs_hash = filesystem::get_path_hash(path)?;
-        let cache_path = filesystem::cache_path().join(format!("{path_hash}.bin"));
+    fn save_to_cache_inner(
+        &self,
+        trie: &Trie,
+        path: &PathBuf,
+        cache_dir: &Path,
+    ) -> anyhow::Result<()> {
+        let path_hash = Self::cache_key(path);
+        let fs_hash = self.source_hash(path)?;
+        let cache_path = cache_dir.join(format!("{path_hash}.bin"));
         trie.dump_to_file(&cache_path)?;
-        let mut cache_hash_store = DictCacheStore::load_from_file(dict_cache_store_location()?)?;
-        cache_hash_store.0.insert(path_hash, fs_hash);
-        cache_hash_store.dump_to_file(dict_cache_store_location()?)?;
-        Ok(())
+        let entry = CacheEntry {
+            hash: fs_hash,
+            source: std::path::absolute(path)?,
+        };
+        DictCacheStore::update_at(&cache_dir.join(CACHE_STORE_NAME), |store| {
+            store.0.insert(path_hash, entry);
+        })
     }
 
-    pub fn save_to_cache(trie: &Trie, path: &PathBuf) -> anyhow::Result<()> {
-        Self::save_to_cache_inner(trie, path)
+    pub fn save_to_cache(
+        &self,
+        trie: &Trie,
+        path: &PathBuf,
+        cache_dir: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        let Some(cache_dir) = cache_dir else {
+            return Ok(());
+        };
+        self.save_to_cache_inner(trie, path, cache_dir)
             .context(format!("Failed to save cache for {}", path.display()))
     }
 
+    /// Clone or update the repository of a git dictionary, see
+    /// [`crate::settings::CustomDictionaryDefinitionGit::init`]. Other
+    /// dictionaries are left alone.
+    pub fn init_git(&self, options: GitOptions) -> anyhow::Result<()> {
+        if let Self::Custom { definition, .. } = self
+            && let CustomDictionaryDefinitionType::Git(git) = &definition.typ
+        {
+            git.init(options).context(format!(
+                "Failed to initialize git dictionary: {}",
+                git.url()
+            ))?;
+        }
+        Ok(())
+    }
+
     pub fn get_names(&self) -> anyhow::Result<Vec<String>> {
         match self {
             Self::File(path) | Self::Trie(path) => Ok(vec![
@@ -268,13 +654,10 @@ impl Dictionary {
         }
     }
 
+    /// Get the file globs this dictionary is scoped to, or `None` if it applies
+    /// to every file.
     pub fn get_globs(&self) -> anyhow::Result<Option<Vec<glob::Pattern>>> {
         match self {
-            Self::File(path) => {
-                let file_name = path.file_name().unwrap().to_string_lossy().to_string();
-                let pattern = glob::Pattern::new(&file_name)?;
-                Ok(Some(vec![pattern]))
-            }
             Self::Directory(path) => {
                 let config_path = path.join("csc-config.json");
                 if !config_path.exists() {
@@ -285,7 +668,7 @@ impl Dictionary {
                 }
                 let content: DictionaryConfig =
                     serde_hjson::from_reader(std::fs::File::open(config_path)?)?;
-                if content.globs.len() > 0 {
+                if !content.globs.is_empty() {
                     let mut patterns = Vec::new();
                     for glob in &content.globs {
                         let pattern = glob::Pattern::new(glob)?;
@@ -297,7 +680,7 @@ impl Dictionary {
                 }
             }
             Self::Custom { definition, .. } => {
-                if definition.globs.len() > 0 {
+                if !definition.globs.is_empty() {
                     let mut patterns = Vec::new();
                     for glob in &definition.globs {
                         let pattern = glob::Pattern::new(glob)?;
@@ -308,14 +691,15 @@ impl Dictionary {
                     Ok(None)
                 }
             }
-            Self::Rules(_) | Self::Trie(_) => Ok(None),
+            // Plain wordlists carry no scope of their own
+            Self::File(_) | Self::Rules(_) | Self::Trie(_) => Ok(None),
         }
     }
 
-    fn compile_inner(&self) -> anyhow::Result<Trie> {
+    fn compile_inner(&self, cache_dir: Option<&Path>) -> anyhow::Result<Trie> {
         match self {
             Self::File(path) => {
-                if let Some(cache) = self.load_from_cache(path)? {
+                if let Some(cache) = self.load_from_cache(path, cache_dir)? {
                     return Ok(cache);
                 }
             }
@@ -329,25 +713,38 @@ impl Dictionary {
                 }
                 let content: DictionaryConfig =
                     serde_hjson::from_reader(std::fs::File::open(config_path)?)?;
-                if !content.no_cache {
-                    if let Some(cache) = self.load_from_cache(path)? {
-                        return Ok(cache);
-                    }
+                if !content.no_cache
+                    && let Some(cache) = self.load_from_cache(path, cache_dir)?
+                {
+                    return Ok(cache);
+                }
+            }
+            Self::Custom { definition, root } => {
+                let path = root.join(definition.path());
+                // Git dictionaries are cloned folders, laid out like installed ones
+                if path.is_dir() {
+                    return Self::Directory(path).compile_inner(cache_dir);
+                }
+                if !definition.no_cache
+                    && path.exists()
+                    && let Some(cache) = self.load_from_cache(&path, cache_dir)?
+                {
+                    return Ok(cache);
                 }
             }
-            Self::Rules(_) | Self::Custom { .. } => {}
+            Self::Rules(_) => {}
             Self::Trie(path) => {
-                if let Some(cache) = self.load_from_cache(path)? {
+                if let Some(cache) = self.load_from_cache(path, cache_dir)? {
                     return Ok(cache);
                 }
             }
         }
         match self {
             Self::File(path) => {
-                let rules = load_dictionary_format_from_file(path)?;
+                let rules = expand_affixes(&load_dictionary_format_from_file(path)?);
                 let trie = Trie::from(rules.as_ref());
                 if trie.options.cache {
-                    Self::save_to_cache(&trie, path)?;
+                    self.save_to_cache(&trie, path, cache_dir)?;
                 }
                 Ok(trie)
             }
@@ -362,7 +759,14 @@ impl Dictionary {
                 }
                 let rules_part = load_dictionary_format_from_file(&path)?;
                 rules.extend(rules_part);
-                Ok(Trie::from(rules.as_ref()))
+                if definition.no_cache {
+                    rules.push(Rule::Command(Command::Cache(false)));
+                }
+                let trie = Trie::from(expand_affixes(&rules).as_ref());
+                if trie.options.cache {
+                    self.save_to_cache(&trie, &path, cache_dir)?;
+                }
+                Ok(trie)
             }
             Self::Directory(path) => {
                 let config_path = path.join("csc-config.json");
@@ -376,19 +780,20 @@ impl Dictionary {
                     serde_hjson::from_reader(std::fs::File::open(config_path)?)?;
                 let mut rules = Vec::new();
                 for path_str in &content.paths {
-                    let path_str = path_str.trim().to_string();
-                    let file_path = relative_path::RelativePath::new(&path_str);
-                    let file_path = file_path.to_path(path);
+                    let file_path = Self::resolve_config_path(path, path_str);
                     if file_path.exists() {
-                        if file_path.extension().unwrap().to_str().unwrap() == "trie" {
-                            let mut trie = crate::cspell::CspellTrie::parse_trie(&file_path)?;
+                        if crate::cspell::is_trie_file(&file_path) {
+                            let mut trie =
+                                crate::cspell::CspellTrie::parse_trie(&file_path).context(
+                                    format!("Failed to parse trie: {}", file_path.display()),
+                                )?;
                             if content.paths.len() != 1 {
                                 bail!("If trie is compiled, there can only be one path");
                             }
                             trie.options.case_sensitive = content.case_sensitive;
                             trie.options.cache = !content.no_cache;
                             if trie.options.cache {
-                                Self::save_to_cache(&trie, path)?;
+                                self.save_to_cache(&trie, path, cache_dir)?;
                             }
                             return Ok(trie);
                         }
@@ -409,33 +814,151 @@ impl Dictionary {
                 } else {
                     rules.push(Rule::Command(Command::Cache(true)));
                 }
-                let trie = Trie::from(rules.as_ref());
+                let trie = Trie::from(expand_affixes(&rules).as_ref());
                 if trie.options.cache {
-                    Self::save_to_cache(&trie, path)?;
+                    self.save_to_cache(&trie, path, cache_dir)?;
                 }
                 Ok(trie)
             }
             Self::Rules(rules) => {
-                let mut new_rules = vec![];
-                for rule in rules {
-                    new_rules.push(rule.clone());
-                }
+                let mut new_rules = expand_affixes(rules);
                 new_rules.push(Rule::Command(Command::Cache(false)));
                 let trie = Trie::from(new_rules.as_ref());
                 Ok(trie)
             }
             Self::Trie(path) => {
+                filesystem::ensure_not_lfs_pointer(path)?;
                 let content = std::fs::read(path)?;
                 let trie = Trie::load(&content)?;
                 if trie.options.cache {
-                    Self::save_to_cache(&trie, path)?;
+                    self.save_to_cache(&trie, path, cache_dir)?;
                 }
                 Ok(trie)
             }
         }
     }
 
+    /// Compile the dictionary, using the cache unless it's disabled by
+    /// `--no-cache`.
     pub fn compile(&self) -> anyhow::Result<Trie> {
-        self.compile_inner().context("Failed to compile dictionary")
+        let cache_dir = cache_enabled().then(filesystem::cache_path);
+        self.compile_in(cache_dir.as_deref())
+    }
+
+    /// Compile the dictionary, reading and writing the compiled trie in
+    /// `cache_dir`, or without a cache if there's none.
+    pub fn compile_in(&self, cache_dir: Option<&Path>) -> anyhow::Result<Trie> {
+        let mut trie = self
+            .compile_inner(cache_dir)
+            .context("Failed to compile dictionary")?;
+        if cache_dir.is_none() {
+            trie.options.cache = false;
+        }
+        Ok(trie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(lines: &[&str]) -> Trie {
+        let lines = lines.iter().map(ToString::to_string).collect::<Vec<_>>();
+        Dictionary::new_from_strings(&lines).compile().unwrap()
+    }
+
+    #[test]
+    fn test_affixes() {
+        let trie = compile(&["# csc: affixes", "run/ing,ner,s", "make/ing", "open/ing"]);
+        for word in ["run", "running", "runner", "runs", "making", "opening"] {
+            assert!(trie.contains(word), "{word}");
+        }
+        assert!(!trie.contains("runing"));
+
+        // Without the command, `/` is part of the word
+        let trie = compile(&["and/or", "TCP/IP", "# csc: affixes", "walk/ing"]);
+        assert!(trie.contains("and/or"));
+        assert!(trie.contains("tcp/ip"));
+        assert!(!trie.contains("and"));
+        assert!(trie.contains("walking"));
+    }
+
+    #[test]
+    fn test_concurrent_cache_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+        let keys = (0..16).map(|i| format!("key-{i}")).collect::<Vec<_>>();
+        std::thread::scope(|scope| {
+            for key in &keys {
+                scope.spawn(|| {
+                    DictCacheStore::update_at(&path, |store| {
+                        let entry = CacheEntry {
+                            hash: "hash".to_string(),
+                            source: PathBuf::from(key.as_str()),
+                        };
+                        store.0.insert(key.clone(), entry);
+                    })
+                    .unwrap();
+                });
+            }
+        });
+        let store = DictCacheStore::load_from_file(&path).unwrap();
+        assert!(keys.iter().all(|key| store.0.contains_key(key)));
+    }
+
+    #[test]
+    fn test_cache_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        std::fs::create_dir(&cache_dir).unwrap();
+        let path = dir.path().join("words.txt");
+        std::fs::write(&path, "hello\n").unwrap();
+        let dictionary = Dictionary::File(path);
+
+        let trie = dictionary.compile_in(None).unwrap();
+        assert!(trie.contains("hello"));
+        assert!(!trie.options.cache);
+        assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 0);
+
+        let trie = dictionary.compile_in(Some(&cache_dir)).unwrap();
+        assert!(trie.options.cache);
+        let key = dictionary.get_cache_key().unwrap();
+        assert!(cache_dir.join(format!("{key}.bin")).exists());
+    }
+
+    #[test]
+    fn test_prune_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        std::fs::create_dir(&cache_dir).unwrap();
+        let kept = dir.path().join("kept.txt");
+        let deleted = dir.path().join("deleted.txt");
+        for path in [&kept, &deleted] {
+            std::fs::write(path, "hello\n").unwrap();
+            Dictionary::File(path.clone())
+                .compile_in(Some(&cache_dir))
+                .unwrap();
+        }
+        std::fs::remove_file(&deleted).unwrap();
+        // Untracked files are only pruned once they're old
+        let old = cache_dir.join("old.bin");
+        let new = cache_dir.join("new.bin");
+        std::fs::write(&new, "").unwrap();
+        std::fs::File::create(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - UNTRACKED_MAX_AGE * 2)
+            .unwrap();
+
+        assert_eq!(DictCacheStore::prune_at(&cache_dir).unwrap(), 2);
+        let bin = |path: &PathBuf| {
+            let key = Dictionary::File(path.clone()).get_cache_key().unwrap();
+            cache_dir.join(format!("{key}.bin"))
+        };
+        assert!(bin(&kept).exists());
+        assert!(!bin(&deleted).exists());
+        assert!(new.exists());
+        assert!(!old.exists());
+        let store = DictCacheStore::load_from_file(cache_dir.join(CACHE_STORE_NAME)).unwrap();
+        assert_eq!(store.0.len(), 1);
     }
 }
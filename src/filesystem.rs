@@ -3,6 +3,10 @@ use std::{
     fs,
     io::Read,
     path::{Path, PathBuf},
+    sync::{
+        Mutex, Once, OnceLock, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use anyhow::{Context, bail};
@@ -13,11 +17,64 @@ pub fn get_file_extension(file: &Path) -> Option<String> {
         .map(ToString::to_string)
 }
 
+/// Set by `--home`
+static HOME_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `path` instead of `CSC_HOME` or `~/.code-spellcheck`. Only takes effect
+/// before the first use of the directory, as the paths below it are cached.
+pub fn set_csc_home(path: PathBuf) {
+    let _ = HOME_OVERRIDE.set(path);
+}
+
+/// Pick the base directory from the `--home` directory, the value of
+/// `CSC_HOME` and the user's home directory, in that order. `None` if there
+/// are none of them.
+fn resolve_csc_path(
+    home_override: Option<&Path>,
+    csc_home: Option<&OsStr>,
+    home: Option<&Path>,
+) -> Option<PathBuf> {
+    if let Some(path) = home_override {
+        return Some(path.to_path_buf());
+    }
+    match csc_home {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => home.map(|home| home.join(".code-spellcheck")),
+    }
+}
+
+/// The base directory for dictionaries and caches: the `--home` directory,
+/// else `CSC_HOME`, else `~/.code-spellcheck`. Without a home directory, as
+/// in minimal containers, a directory under the system's temporary directory
+/// is used instead.
 pub fn csc_path() -> PathBuf {
-    let mut path = std::env::home_dir().expect("Failed to get home directory");
-    path.push(".code-spellcheck");
+    static NO_HOME_WARNING: Once = Once::new();
+
+    let csc_home = std::env::var_os("CSC_HOME");
+    let home = std::env::home_dir();
+    let path = resolve_csc_path(
+        HOME_OVERRIDE.get().map(PathBuf::as_path),
+        csc_home.as_deref(),
+        home.as_deref(),
+    )
+    .unwrap_or_else(|| {
+        let path = std::env::temp_dir().join("code-spellcheck");
+        NO_HOME_WARNING.call_once(|| {
+            eprintln!(
+                "Failed to get the home directory, using {} instead. Set CSC_HOME to choose \
+                 another directory",
+                path.display()
+            );
+        });
+        path
+    });
     if !path.exists() {
-        fs::create_dir_all(&path).expect("Failed to create .code-spellcheck directory");
+        fs::create_dir_all(&path).unwrap_or_else(|e| {
+            panic!(
+                "Failed to create {}: {e}. Set CSC_HOME to a writable directory",
+                path.display()
+            )
+        });
     }
     path
 }
@@ -29,7 +86,12 @@ macro_rules! subpath {
         pub fn $name() -> PathBuf {
             let path = csc_path().join($path);
             if !path.exists() {
-                fs::create_dir_all(&path).expect("Failed to create $name directory");
+                fs::create_dir_all(&path).unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to create {}: {e}. Set CSC_HOME to a writable directory",
+                        path.display()
+                    )
+                });
             }
             path
         }
@@ -43,6 +105,64 @@ subpath!(cspell_path, "custom-dicts/cspell");
 subpath!(download_path, "custom-dicts/download");
 subpath!(git_path, "custom-dicts/git");
 
+/// Write `data` to a temporary file next to `path` and rename it into place,
+/// so readers never see a partially written file.
+pub fn write_atomic<P: AsRef<Path>>(path: P, data: &[u8]) -> std::io::Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let path = path.as_ref();
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
+
+/// Run `f` holding the lock file next to `path`, so threads and processes
+/// updating the file at the same time take turns.
+pub fn with_lock<T>(path: &Path, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    // File locks are held per process, so threads take turns first
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    let _guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let lock_path = path.with_extension("lock");
+    let lock = fs::File::create(&lock_path)
+        .context(format!("Failed to create {}", lock_path.display()))?;
+    lock.lock()
+        .context(format!("Failed to lock {}", lock_path.display()))?;
+    f()
+}
+
+/// Fail if `path` is a Git LFS pointer rather than the file's actual content.
+pub fn ensure_not_lfs_pointer<P: AsRef<Path>>(path: P) -> anyhow::Result<()> {
+    const LFS_POINTER_PREFIX: &[u8] = b"version https://git-lfs.";
+
+    let path = path.as_ref();
+    let mut file = fs::File::open(path).context("Failed to open file")?;
+    let mut buffer = [0; LFS_POINTER_PREFIX.len()];
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    if buffer[..read] == *LFS_POINTER_PREFIX {
+        bail!(
+            "{} is a Git LFS pointer, not the dictionary itself. Fetch the LFS content (e.g. \
+             with `git lfs pull`) and try again",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
 pub fn get_path_hash<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
     if !path.as_ref().exists() {
         bail!("Path does not exist: {}", path.as_ref().display());
@@ -83,3 +203,44 @@ pub fn get_path_hash<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
     }
     Ok(hasher.finalize().to_hex().to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csc_home() {
+        let home = Path::new("/home/user");
+        assert_eq!(
+            resolve_csc_path(None, None, Some(home)),
+            Some(home.join(".code-spellcheck"))
+        );
+        // An empty `CSC_HOME` is ignored
+        assert_eq!(
+            resolve_csc_path(None, Some(OsStr::new("")), Some(home)),
+            Some(home.join(".code-spellcheck"))
+        );
+        assert_eq!(
+            resolve_csc_path(None, Some(OsStr::new("/ci/csc")), Some(home)),
+            Some(PathBuf::from("/ci/csc"))
+        );
+        assert_eq!(
+            resolve_csc_path(Some(Path::new("/flag")), Some(OsStr::new("/ci/csc")), None),
+            Some(PathBuf::from("/flag"))
+        );
+        assert_eq!(resolve_csc_path(None, None, None), None);
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("csc-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.json");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        // The temporary files were renamed into place
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+/// The strings of a gettext catalog (`.po` or `.pot`) that are checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// The source strings (`msgid` and `msgid_plural`)
+    Msgid,
+    /// The translations (`msgstr` and `msgstr[N]`)
+    Msgstr,
+}
+
+impl Field {
+    fn of(keyword: &str) -> Option<Self> {
+        if keyword == "msgid" || keyword == "msgid_plural" {
+            Some(Self::Msgid)
+        } else if keyword == "msgstr" || keyword.starts_with("msgstr[") {
+            Some(Self::Msgstr)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extract the strings of `field` from a gettext catalog, skipping the
+/// catalog header.
+///
+/// Each string is placed on the line of the keyword that starts it, with
+/// continuation lines joined onto it and every other line left empty, so
+/// line numbers in the result match the catalog.
+pub fn extract(content: &str, field: Field) -> String {
+    let mut lines = vec![String::new(); content.lines().count()];
+    // The keyword of the string being continued, and its line if recorded
+    let mut current: Option<(&str, Option<usize>)> = None;
+    let mut msgid = String::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        // Obsolete entries are commented out with `#~`
+        let line = line.strip_prefix("#~").map_or(line, str::trim_start);
+        if line.starts_with('"') {
+            if let Some((keyword, start)) = current {
+                let text = unquote(line);
+                if keyword == "msgid" {
+                    msgid.push_str(&text);
+                }
+                if let Some(start) = start {
+                    lines[start].push_str(&text);
+                }
+            }
+            continue;
+        }
+        current = None;
+        let Some((keyword, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let Some(keyword_field) = Field::of(keyword) else {
+            continue;
+        };
+        let text = unquote(rest.trim());
+        if keyword == "msgid" {
+            msgid = text.clone();
+        }
+        // The header is the translation of the empty msgid
+        let is_header = keyword_field == Field::Msgstr && msgid.is_empty();
+        if keyword_field == field && !is_header {
+            lines[index] = text;
+            current = Some((keyword, Some(index)));
+        } else {
+            current = Some((keyword, None));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Unquote a C-style string literal, turning escapes into whitespace or the
+/// escaped character.
+fn unquote(literal: &str) -> String {
+    let literal = literal.strip_prefix('"').unwrap_or(literal);
+    let literal = literal.strip_suffix('"').unwrap_or(literal);
+    let mut text = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 't' | 'r') => text.push(' '),
+            Some(c) => text.push(c),
+            None => {}
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CATALOG: &str = r#"# Translation of an app
+msgid ""
+msgstr ""
+"Content-Type: text/plain; charset=UTF-8\n"
+
+#: src/main.rs:10
+msgid "Helo world"
+msgstr "Hallo Welt"
+
+msgid ""
+"First line\n"
+"second line"
+msgid_plural "Many \"lines\""
+msgstr[0] "Erste"
+msgstr[1] "Zweite"
+
+#~ msgid "Old entry"
+#~ msgstr "Alter Eintrag"
+"#;
+
+    #[test]
+    fn test_extract_msgid() {
+        let text = extract(CATALOG, Field::Msgid);
+        let lines = text.split('\n').collect::<Vec<_>>();
+        assert_eq!(lines.len(), CATALOG.lines().count());
+        assert_eq!(lines[1], "");
+        assert_eq!(lines[6], "Helo world");
+        assert_eq!(lines[7], "");
+        assert_eq!(lines[9], "First line second line");
+        assert_eq!(lines[10], "");
+        assert_eq!(lines[12], "Many \"lines\"");
+        assert_eq!(lines[16], "Old entry");
+    }
+
+    #[test]
+    fn test_extract_msgstr() {
+        let text = extract(CATALOG, Field::Msgstr);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[2], "");
+        assert_eq!(lines[3], "");
+        assert_eq!(lines[7], "Hallo Welt");
+        assert_eq!(lines[13], "Erste");
+        assert_eq!(lines[14], "Zweite");
+        assert_eq!(lines[17], "Alter Eintrag");
+    }
+}
@@ -4,6 +4,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use anyhow::Context;
 use git2::Repository;
 
 struct State {
@@ -15,14 +16,19 @@ struct State {
 }
 
 fn print(state: &mut State) {
-    let statistics = state.progress.as_ref().unwrap();
-    let network_pct = (100 * statistics.received_objects()) / statistics.total_objects();
-    let index_pct = (100 * statistics.indexed_objects()) / statistics.total_objects();
-    let co_pct = if state.total > 0 {
-        (100 * state.current) / state.total
-    } else {
-        0
+    let co_pct = (100 * state.current).checked_div(state.total).unwrap_or(0);
+    // Local clones check out without transferring anything
+    let Some(statistics) = state.progress.as_ref() else {
+        print!("chk {co_pct:3}% ({:4}/{:4})\r", state.current, state.total);
+        std::io::stdout().flush().unwrap();
+        return;
     };
+    let network_pct = (100 * statistics.received_objects())
+        .checked_div(statistics.total_objects())
+        .unwrap_or(0);
+    let index_pct = (100 * statistics.indexed_objects())
+        .checked_div(statistics.total_objects())
+        .unwrap_or(0);
     let kilobytes = statistics.received_bytes() / 1024;
     if statistics.received_objects() == statistics.total_objects() {
         if !state.newline {
@@ -56,7 +62,123 @@ fn print(state: &mut State) {
     std::io::stdout().flush().unwrap();
 }
 
-pub fn clone<P: AsRef<Path>>(url: &str, path: P) -> Result<git2::Repository, git2::Error> {
+/// Authenticate with keys from the SSH agent for SSH remotes, and with the
+/// `GIT_TOKEN` environment variable or git's credential helpers for HTTPS
+/// remotes. `GIT_USERNAME` overrides the user name given with a token.
+fn authenticate(callbacks: &mut git2::RemoteCallbacks) {
+    // libgit2 asks again after every rejected credential
+    let mut attempts = 0;
+    callbacks.credentials(move |url, username, allowed| {
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str(&format!(
+                "Failed to authenticate with {url}"
+            )));
+        }
+        // SSH remotes without a user name ask for one first
+        if allowed.contains(git2::CredentialType::USERNAME) {
+            return git2::Cred::username(username.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            return git2::Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            if let Ok(token) = std::env::var("GIT_TOKEN")
+                && attempts == 1
+            {
+                let username = std::env::var("GIT_USERNAME")
+                    .ok()
+                    .or_else(|| username.map(str::to_string))
+                    .unwrap_or_else(|| "x-access-token".to_string());
+                return git2::Cred::userpass_plaintext(&username, &token);
+            }
+            let config = git2::Config::open_default()?;
+            return git2::Cred::credential_helper(&config, url, username);
+        }
+        git2::Cred::default()
+    });
+}
+
+/// Open the repository containing `dir`, along with its canonical working
+/// directory.
+fn discover(dir: &Path) -> anyhow::Result<(Repository, PathBuf)> {
+    let repo = Repository::discover(dir)
+        .with_context(|| format!("Not in a git repository: {}", dir.display()))?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow::anyhow!("Can't check files of a bare repository"))?
+        .canonicalize()?;
+    Ok((repo, workdir))
+}
+
+/// The files a diff adds or changes, as absolute paths. Deleted files are
+/// left out and renamed files are listed by their new path.
+fn diff_files(diff: &git2::Diff, workdir: &Path) -> Vec<PathBuf> {
+    diff.deltas()
+        .filter(|delta| delta.status() != git2::Delta::Deleted)
+        .filter_map(|delta| delta.new_file().path().map(|path| workdir.join(path)))
+        .collect()
+}
+
+/// The files staged in the repository containing `dir`, as absolute paths.
+/// Deleted files are left out.
+pub fn staged_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (repo, workdir) = discover(dir)?;
+    // Before the first commit everything in the index is staged
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e).context("Failed to read HEAD"),
+    };
+    let mut diff = repo
+        .diff_tree_to_index(head.as_ref(), None, None)
+        .context("Failed to diff HEAD against the index")?;
+    diff.find_similar(None)?;
+    Ok(diff_files(&diff, &workdir))
+}
+
+/// The files in the working tree of the repository containing `dir` that
+/// changed since `reference`, as absolute paths. Like `git diff
+/// <reference>...`, changes are counted from where `HEAD` branched off
+/// `reference`. Deleted files are left out.
+pub fn changed_since(dir: &Path, reference: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let (repo, workdir) = discover(dir)?;
+    let since = repo
+        .revparse_single(reference)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("Unknown git reference: {reference}"))?;
+    let base = match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(head) => repo
+            .find_commit(repo.merge_base(since.id(), head.id())?)?
+            .tree()?,
+        Err(_) => since.tree()?,
+    };
+    let mut diff = repo
+        .diff_tree_to_workdir_with_index(Some(&base), None)
+        .with_context(|| format!("Failed to diff against {reference}"))?;
+    diff.find_similar(None)?;
+    Ok(diff_files(&diff, &workdir))
+}
+
+/// Clone `url` into `path` with the last `depth` commits, or all of them if
+/// `depth` is 0. Servers that reject shallow fetches are cloned in full.
+pub fn clone<P: AsRef<Path>>(
+    url: &str,
+    path: P,
+    depth: u32,
+) -> Result<git2::Repository, git2::Error> {
+    match clone_with_depth(url, path.as_ref(), depth) {
+        Err(e) if depth > 0 && e.message().contains("shallow") => {
+            println!("Shallow clone not supported, cloning the full history");
+            // Remove what the failed clone left behind
+            let _ = std::fs::remove_dir_all(path.as_ref());
+            clone_with_depth(url, path.as_ref(), 0)
+        }
+        result => result,
+    }
+}
+
+fn clone_with_depth(url: &str, path: &Path, depth: u32) -> Result<git2::Repository, git2::Error> {
     let state = RefCell::new(State {
         progress: None,
         total: 0,
@@ -65,6 +187,7 @@ pub fn clone<P: AsRef<Path>>(url: &str, path: P) -> Result<git2::Repository, git
         newline: false,
     });
     let mut cb = git2::RemoteCallbacks::new();
+    authenticate(&mut cb);
     cb.transfer_progress(|stats| {
         let mut state = state.borrow_mut();
         state.progress = Some(stats.to_owned());
@@ -82,13 +205,12 @@ pub fn clone<P: AsRef<Path>>(url: &str, path: P) -> Result<git2::Repository, git
     });
 
     let mut fo = git2::FetchOptions::new();
-    // Shallow clone
-    fo.depth(1);
+    fo.depth(i32::try_from(depth).unwrap_or(i32::MAX));
     fo.remote_callbacks(cb);
     let repo = git2::build::RepoBuilder::new()
         .fetch_options(fo)
         .with_checkout(co)
-        .clone(url, path.as_ref())?;
+        .clone(url, path)?;
     println!();
 
     Ok(repo)
@@ -100,6 +222,7 @@ pub fn fetch<'a>(
     remote: &'a mut git2::Remote,
 ) -> Result<git2::AnnotatedCommit<'a>, git2::Error> {
     let mut cb = git2::RemoteCallbacks::new();
+    authenticate(&mut cb);
 
     // Print out our transfer progress.
     cb.transfer_progress(|stats| {
@@ -155,6 +278,12 @@ pub fn fetch<'a>(
     repo.reference_to_annotated_commit(&fetch_head)
 }
 
+/// Check out `commit` with a detached HEAD, discarding local changes.
+pub fn checkout_detached(repo: &Repository, commit: git2::Oid) -> Result<(), git2::Error> {
+    repo.set_head_detached(commit)?;
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))
+}
+
 fn fast_forward(
     repo: &Repository,
     lb: &mut git2::Reference,
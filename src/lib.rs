@@ -0,0 +1,1040 @@
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use dashmap::DashMap;
+use regex::Regex;
+use tokio::{
+    sync::{Mutex, Semaphore},
+    task::JoinSet,
+    time::Instant,
+};
+
+mod args;
+mod autocorrect;
+mod checker;
+mod cli;
+mod code;
+mod cspell;
+mod dictionary;
+mod filesystem;
+mod gettext;
+mod git;
+#[cfg(feature = "lsp")]
+mod lsp;
+mod multi_trie;
+mod notebook;
+mod report;
+mod result_cache;
+mod settings;
+mod trie;
+mod unified_diff;
+
+pub use checker::Checker;
+// The binary's entry point, which isn't part of the library API
+#[doc(hidden)]
+pub use cli::run;
+pub use code::{Prose, Typo, TypoCategory, check_text, detect_language, get_code, handle_node};
+pub use dictionary::Dictionary;
+use filesystem::{cache_path, store_path};
+pub use multi_trie::{MultiTrie, SuggestionCache, WordUsage};
+pub use report::CheckFileResult;
+pub use settings::Settings;
+pub use trie::Trie;
+
+use crate::{
+    args::{ContextArgs, LibraryArgs},
+    code::{
+        get_parser, get_parser_for_path, handle_leetspeak, handle_leetspeak_text, handle_prose,
+        handle_text, inline_words, prose_node_kinds, skipped_node_kinds,
+    },
+    dictionary::{Rule, load_dictionary_format_from_file, merge_rules},
+    notebook::Notebook,
+    result_cache::ResultCache,
+    settings::DictionaryName,
+};
+
+pub type HashSet<T> = ahash::HashSet<T>;
+pub type HashMap<K, V> = ahash::HashMap<K, V>;
+
+/// Load every dictionary in the store, logging and skipping unreadable
+/// entries.
+fn store_dictionaries() -> Vec<Dictionary> {
+    folder_dictionaries(&store_path())
+}
+
+/// Load every dictionary in a folder laid out like the store, logging and
+/// skipping unreadable entries.
+fn folder_dictionaries(folder: &Path) -> Vec<Dictionary> {
+    let mut dictionaries = Vec::new();
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read dictionary folder {}: {e}", folder.display());
+            return dictionaries;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Failed to read dictionary folder entry: {e}");
+                continue;
+            }
+        };
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "bin") {
+            continue;
+        }
+        match Dictionary::new_with_path(path) {
+            Ok(dictionary) => dictionaries.push(dictionary),
+            Err(e) => {
+                eprintln!("Failed to load dictionary from {}: {e}", folder.display());
+            }
+        }
+    }
+    dictionaries
+}
+
+/// Find a dictionary by name among the settings' definitions and the store,
+/// falling back to treating `name` as a path.
+fn find_dictionary(name: &str, settings: &Settings) -> anyhow::Result<Dictionary> {
+    let root = std::env::current_dir()?;
+    let definitions = settings
+        .dictionary_definitions
+        .iter()
+        .map(|def| Dictionary::new_custom(def.clone(), root.clone()));
+    let project = settings
+        .dictionary_dir
+        .iter()
+        .flat_map(|dir| folder_dictionaries(&root.join(dir)));
+    for dictionary in definitions.chain(project).chain(store_dictionaries()) {
+        if dictionary
+            .get_names()
+            .is_ok_and(|names| names.iter().any(|n| n == name))
+        {
+            return Ok(dictionary);
+        }
+    }
+    let path = PathBuf::from(name);
+    if path.exists() {
+        return Dictionary::new_with_path(path);
+    }
+    bail!("Dictionary not found: {name}");
+}
+
+/// Find a dictionary like [`find_dictionary`], cloning or updating it first
+/// if it's a git dictionary.
+fn load_dictionary(name: &str, settings: &Settings) -> anyhow::Result<Dictionary> {
+    let dictionary = find_dictionary(name, settings)?;
+    dictionary.init_git(settings.git_options())?;
+    Ok(dictionary)
+}
+
+struct MergedSettings {
+    args: Box<dyn ContextArgs + Send + Sync>,
+    settings: Settings,
+}
+
+impl MergedSettings {
+    fn new(args: Box<dyn ContextArgs + Send + Sync>, settings: Settings) -> Self {
+        Self { args, settings }
+    }
+
+    /// The project-local allowlist, `code-spellcheck.words.txt` next to the
+    /// settings file. Loaded when present, and appended to by the language
+    /// server's "add to project words" action.
+    fn project_words_path(&self) -> PathBuf {
+        let settings = self
+            .args
+            .settings()
+            .unwrap_or_else(settings::default_settings_path);
+        settings
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("code-spellcheck.words.txt")
+    }
+
+    /// Append `word` to the project words file, creating it if needed.
+    #[cfg(feature = "lsp")]
+    fn add_project_word(&self, word: &str) -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let path = self.project_words_path();
+        let existing = std::fs::read_to_string(&path).unwrap_or_default();
+        // Keep the word off an unterminated last line
+        let separator = if existing.is_empty() || existing.ends_with('\n') {
+            ""
+        } else {
+            "\n"
+        };
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{separator}{word}"))
+            .context(format!(
+                "Failed to add to project words: {}",
+                path.display()
+            ))
+    }
+
+    fn root_path(&self) -> PathBuf {
+        if self.args.dir().is_absolute() {
+            self.args.dir()
+        } else {
+            std::env::current_dir().unwrap()
+        }
+    }
+
+    fn dictionaries(&self) -> Vec<Dictionary> {
+        let mut dictionaries = Vec::with_capacity(
+            self.args.extra_dictionaries().len() + self.settings.dictionary_definitions.len(),
+        );
+        for extra in &self.args.extra_dictionaries() {
+            if let Ok(dictionary) = Dictionary::new_with_path(PathBuf::from(extra)) {
+                dictionaries.push(dictionary);
+            }
+        }
+        for def in &self.settings.dictionary_definitions {
+            dictionaries.push(Dictionary::new_custom(def.clone(), self.root_path()));
+        }
+        if let Some(dir) = self.dictionary_dir() {
+            dictionaries.extend(folder_dictionaries(&dir));
+        }
+        if self.args.dictionaries_from_config_only() {
+            return dictionaries;
+        }
+        dictionaries.extend(store_dictionaries());
+        dictionaries
+    }
+
+    /// The project's dictionary folder from `--dictionary-dir` or the
+    /// settings, relative to the root.
+    fn dictionary_dir(&self) -> Option<PathBuf> {
+        let dir = self
+            .args
+            .dictionary_dir()
+            .or_else(|| self.settings.dictionary_dir.clone())?;
+        Some(self.root_path().join(dir))
+    }
+
+    fn base_dictionaries(&self) -> Vec<String> {
+        let only = self.args.only_dictionaries();
+        let mut dictionaries = if only.is_empty() {
+            let mut dictionaries = self
+                .settings
+                .dictionaries
+                .iter()
+                .map(DictionaryName::name)
+                .collect::<Vec<_>>();
+            dictionaries.extend(self.args.extra_dictionaries());
+            dictionaries
+        } else {
+            only
+        };
+        let disabled = self.args.disabled_dictionaries();
+        dictionaries.retain(|name| !disabled.contains(name));
+        dictionaries
+    }
+
+    /// The dictionaries enabled for files of `language`, unless dictionaries
+    /// were chosen with `--only-dict`.
+    fn language_dictionaries(&self, language: &str) -> Vec<String> {
+        if !self.args.only_dictionaries().is_empty() {
+            return Vec::new();
+        }
+        let mut dictionaries = self
+            .settings
+            .language_settings
+            .get(language)
+            .cloned()
+            .unwrap_or_default();
+        let disabled = self.args.disabled_dictionaries();
+        dictionaries.retain(|name| !disabled.contains(name));
+        dictionaries
+    }
+
+    fn verbose(&self) -> bool {
+        self.args.verbose()
+    }
+
+    fn strip_prefixes(&self) -> Vec<String> {
+        let mut prefixes = self.settings.strip_prefixes.clone();
+        prefixes.extend(self.args.strip_prefixes());
+        prefixes
+    }
+
+    fn suggestion_threshold(&self) -> Option<f64> {
+        self.args
+            .suggestion_threshold()
+            .or(self.settings.suggestion_threshold)
+    }
+
+    /// The node kinds to check in files of `language`, or `None` to check
+    /// everything.
+    fn prose_kinds(&self, language: &str) -> Option<Vec<String>> {
+        let prose = self.args.prose_only()?;
+        let mut kinds = prose_node_kinds(language, prose)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if let Some(extra) = self.settings.prose_node_kinds.get(language) {
+            kinds.extend(match prose {
+                Prose::Comments => extra.comments.iter().cloned(),
+                Prose::Strings => extra.strings.iter().cloned(),
+            });
+        }
+        Some(kinds)
+    }
+
+    /// The node kinds never checked in files of `language`.
+    fn skipped_kinds(&self, language: &str) -> Vec<String> {
+        let mut kinds = skipped_node_kinds(language, self.args.prose_only())
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if let Some(extra) = self.settings.skip_node_kinds.get(language) {
+            kinds.extend(extra.iter().cloned());
+        }
+        kinds
+    }
+
+    /// The number of worker threads, where `--jobs 0` means one per core and
+    /// large values are capped to a small multiple of the core count.
+    fn jobs(&self) -> usize {
+        let cores = num_cpus::get();
+        let max = cores * 4;
+        match self.args.jobs() {
+            None | Some(0) => cores,
+            Some(jobs) if jobs > max => {
+                eprintln!("--jobs {jobs} is too many, using {max} threads instead");
+                max
+            }
+            Some(jobs) => jobs,
+        }
+    }
+}
+
+/// Upper bound on the number of misspelled words with a cached suggestion.
+const SUGGESTION_CACHE_CAPACITY: usize = 10_000;
+
+struct SharedRuntimeContext {
+    // None means the dictionary is not loaded
+    dictionaries: DashMap<String, Arc<Trie>>,
+    /// File globs for dictionaries that only apply to some files
+    scopes: DashMap<String, Vec<glob::Pattern>>,
+    /// Allowlist rules shared by every file, set by `load_dictionaries`
+    custom_rules: OnceLock<Vec<Rule>>,
+    custom_trie: OnceLock<Arc<Trie>>,
+    /// Unions of dictionaries, keyed by the names they combine
+    flattened: DashMap<Vec<String>, Arc<Trie>>,
+    /// Compiled `patterns` from the settings, set by `load_dictionaries`
+    patterns: OnceLock<Vec<Regex>>,
+    /// Suggestions for misspelled words, shared by every file
+    suggestions: Arc<SuggestionCache>,
+    /// Set to stop the workers from checking more files
+    cancelled: AtomicBool,
+    /// Allowlist words looked up while checking, set by `track_word_usage`
+    usage: OnceLock<Arc<WordUsage>>,
+    /// Globs of each entry of `overrides` with its words, set by
+    /// `load_dictionaries`
+    overrides: OnceLock<Vec<(Vec<glob::Pattern>, Arc<Trie>)>>,
+    /// Results of unchanged files, set by `check` unless disabled
+    results: OnceLock<ResultCache>,
+    settings: MergedSettings,
+}
+
+impl SharedRuntimeContext {
+    fn new(settings: MergedSettings) -> Self {
+        let dictionaries = DashMap::new();
+        Self {
+            dictionaries,
+            scopes: DashMap::new(),
+            custom_rules: OnceLock::new(),
+            custom_trie: OnceLock::new(),
+            flattened: DashMap::new(),
+            patterns: OnceLock::new(),
+            suggestions: Arc::new(SuggestionCache::new(SUGGESTION_CACHE_CAPACITY)),
+            cancelled: AtomicBool::new(false),
+            usage: OnceLock::new(),
+            overrides: OnceLock::new(),
+            results: OnceLock::new(),
+            settings,
+        }
+    }
+
+    /// Collect the allowlist rules that apply to every file.
+    ///
+    /// Sources are merged from least to most specific: settings `words`, the
+    /// project words file, `--ignore-words-list` files, then `--word` flags. A
+    /// later source overrides an earlier one for the same word.
+    fn load_custom_rules(&self) -> anyhow::Result<()> {
+        let mut rules = Dictionary::rules_from_strings(&self.settings.settings.words);
+        let project_words = self.settings.project_words_path();
+        if project_words.exists() {
+            rules.extend(
+                load_dictionary_format_from_file(&project_words).context(format!(
+                    "Failed to load project words: {}",
+                    project_words.display()
+                ))?,
+            );
+        }
+        for path in self.settings.args.ignore_words_lists() {
+            rules.extend(load_dictionary_format_from_file(&path).context(format!(
+                "Failed to load ignore words list: {}",
+                path.display()
+            ))?);
+        }
+        rules.extend(Dictionary::rules_from_strings(&self.settings.args.words()));
+        let rules = merge_rules(rules);
+        let trie = Dictionary::new_with_rules(rules.clone()).compile()?;
+        let _ = self.custom_trie.set(Arc::new(trie));
+        let _ = self.custom_rules.set(rules);
+        Ok(())
+    }
+
+    /// Track which allowlist words are looked up: the project's words and the
+    /// dictionaries of `dictionary_definitions`. Called once the
+    /// dictionaries are loaded.
+    fn track_word_usage(&self) {
+        let mut tries = Vec::new();
+        if let Some(trie) = self.custom_trie.get() {
+            tries.push(("words".to_string(), trie.clone()));
+        }
+        for definition in &self.settings.settings.dictionary_definitions {
+            if let Some(trie) = self.dictionaries.get(&definition.name) {
+                tries.push((definition.name.clone(), trie.clone()));
+            }
+        }
+        let _ = self.usage.set(Arc::new(WordUsage::new(tries)));
+    }
+
+    /// Hash of everything besides a file's content that its typos depend on:
+    /// the loaded dictionaries, the allowlist, the settings, and the
+    /// arguments that change how words are checked. Called once the
+    /// dictionaries are loaded.
+    fn result_context_hash(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        let mut dictionaries = self
+            .dictionaries
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().content_hash()))
+            .collect::<Vec<_>>();
+        dictionaries.sort();
+        for (name, hash) in dictionaries {
+            hasher.update(name.as_bytes());
+            hasher.update(hash.as_bytes());
+        }
+        if let Some(trie) = self.custom_trie.get() {
+            hasher.update(trie.content_hash().as_bytes());
+        }
+        // Object keys are sorted, so equal settings serialize the same
+        if let Ok(settings) = serde_json::to_value(&self.settings.settings) {
+            hasher.update(settings.to_string().as_bytes());
+        }
+        let args = &self.settings.args;
+        let options = format!(
+            "{:?} {:?} {:?} {:?} {:?} {:?}",
+            args.leetspeak(),
+            args.min_word_frequency(),
+            self.settings.suggestion_threshold(),
+            args.prose_only(),
+            self.settings.strip_prefixes(),
+            self.get_base_dictionaries(),
+        );
+        hasher.update(options.as_bytes());
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Get the union of the named dictionaries, building it on first use.
+    fn flattened(&self, names: &[String]) -> anyhow::Result<Arc<Trie>> {
+        if let Some(trie) = self.flattened.get(names) {
+            return Ok(trie.clone());
+        }
+        // Missing dictionaries were reported by `load_dictionaries`
+        let tries = names
+            .iter()
+            .filter_map(|name| self.dictionaries.get(name).map(|trie| trie.clone()))
+            .collect::<Vec<_>>();
+        let trie = Arc::new(Trie::union(tries.iter().map(AsRef::as_ref))?);
+        self.flattened.insert(names.to_vec(), trie.clone());
+        Ok(trie)
+    }
+
+    fn load_patterns(&self) -> anyhow::Result<()> {
+        let patterns = self
+            .settings
+            .settings
+            .patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern).context(format!("Invalid pattern `{pattern}`")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let _ = self.patterns.set(patterns);
+        Ok(())
+    }
+
+    /// Get the allowlist trie for a file, with the file's inline directives
+    /// (`cspell:words`, `csc:ignore`, ...) taking precedence over all other
+    /// sources.
+    fn custom_trie(&self, source_code: Option<&str>) -> anyhow::Result<Arc<Trie>> {
+        let (Some(rules), Some(trie)) = (self.custom_rules.get(), self.custom_trie.get()) else {
+            bail!("Custom words have not been loaded");
+        };
+        let inline = source_code.map(inline_words).unwrap_or_default();
+        if inline.is_empty() {
+            return Ok(trie.clone());
+        }
+        let rules = merge_rules(
+            rules
+                .iter()
+                .cloned()
+                .chain(Dictionary::rules_from_strings(&inline)),
+        );
+        Ok(Arc::new(Dictionary::new_with_rules(rules).compile()?))
+    }
+
+    fn get_base_dictionaries(&self) -> Vec<String> {
+        self.settings.base_dictionaries()
+    }
+
+    /// The dictionaries for a file: the base dictionaries, and those of its
+    /// language that are installed.
+    fn get_file_dictionaries(&self, file: &Path) -> Vec<String> {
+        let mut dictionaries = self.get_base_dictionaries();
+        if let Some(language) = detect_language(file, &self.settings.settings.language_map) {
+            for name in self.settings.language_dictionaries(&language) {
+                if self.dictionaries.contains_key(&name) && !dictionaries.contains(&name) {
+                    dictionaries.push(name);
+                }
+            }
+        }
+        dictionaries
+    }
+
+    /// Record the globs of a scoped dictionary. Globs given alongside the
+    /// dictionary name in the settings take precedence over the dictionary's
+    /// own.
+    fn load_scopes(&self) -> anyhow::Result<()> {
+        for name in &self.settings.settings.dictionaries {
+            if let DictionaryName::Detailed { name, globs } = name
+                && !globs.is_empty()
+            {
+                let patterns = globs
+                    .iter()
+                    .map(|glob| glob::Pattern::new(glob))
+                    .collect::<Result<Vec<_>, _>>()
+                    .context(format!("Invalid glob for dictionary: {name}"))?;
+                self.scopes.insert(name.clone(), patterns);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile the words of each entry of `overrides`.
+    fn load_overrides(&self) -> anyhow::Result<()> {
+        let mut overrides = Vec::new();
+        for entry in &self.settings.settings.overrides {
+            let patterns = entry
+                .globs
+                .iter()
+                .map(|glob| glob::Pattern::new(glob))
+                .collect::<Result<Vec<_>, _>>()
+                .context(format!("Invalid glob for override: {:?}", entry.globs))?;
+            let trie = Dictionary::new_from_strings(&entry.words).compile()?;
+            overrides.push((patterns, Arc::new(trie)));
+        }
+        let _ = self.overrides.set(overrides);
+        Ok(())
+    }
+
+    /// The entries of `overrides` that apply to `path`, with their words.
+    fn file_overrides(&self, path: &Path) -> Vec<(&settings::Override, Arc<Trie>)> {
+        let Some(overrides) = self.overrides.get() else {
+            return Vec::new();
+        };
+        self.settings
+            .settings
+            .overrides
+            .iter()
+            .zip(overrides)
+            .filter(|(_, (globs, _))| self.matches_globs(globs, path))
+            .map(|(entry, (_, trie))| (entry, trie.clone()))
+            .collect()
+    }
+
+    /// Whether the named dictionary should be consulted for `path`.
+    fn in_scope(&self, name: &str, path: &Path) -> bool {
+        let Some(globs) = self.scopes.get(name) else {
+            return true;
+        };
+        self.matches_globs(&globs, path)
+    }
+
+    /// Whether `path`, relative to the project root, or its file name matches
+    /// any of `globs`.
+    fn matches_globs(&self, globs: &[glob::Pattern], path: &Path) -> bool {
+        let root = self.settings.root_path();
+        let relative = path
+            .strip_prefix(&root)
+            .or_else(|_| path.strip_prefix("."))
+            .unwrap_or(path);
+        let file_name = path.file_name().map(Path::new);
+        globs.iter().any(|glob| {
+            glob.matches_path(relative) || file_name.is_some_and(|name| glob.matches_path(name))
+        })
+    }
+
+    fn get_dictionaries(&self) -> Vec<Dictionary> {
+        self.settings.dictionaries()
+    }
+}
+
+struct FileToCheck {
+    path: PathBuf,
+    /// Source to check instead of the file's content
+    source: Option<String>,
+}
+
+/// Combine the named dictionaries that apply to `path` with the allowlist.
+fn get_multi_trie<P: AsRef<Path>>(
+    mut tries: Vec<String>,
+    path: Option<P>,
+    source_code: Option<&str>,
+    context: Arc<SharedRuntimeContext>,
+) -> anyhow::Result<MultiTrie> {
+    if let Some(ref path) = path
+        && path.as_ref().is_dir()
+    {
+        bail!("Path is a directory: {}", path.as_ref().display());
+    }
+    let mut trie = MultiTrie::new();
+    trie.strip_prefixes = context.settings.strip_prefixes();
+    trie.patterns = context.patterns.get().cloned().unwrap_or_default();
+    trie.suggestion_cache = Some(context.suggestions.clone());
+    trie.min_frequency = context.settings.args.min_word_frequency();
+    trie.suggestion_threshold = context.settings.suggestion_threshold();
+    trie.usage = context.usage.get().cloned();
+    let mut override_tries = Vec::new();
+    if let Some(ref path) = path {
+        for (entry, words) in context.file_overrides(path.as_ref()) {
+            for name in &entry.dictionaries {
+                if !tries.contains(name) {
+                    tries.push(name.clone());
+                }
+            }
+            override_tries.push(words);
+        }
+        tries.retain(|name| context.in_scope(name, path.as_ref()));
+    }
+
+    if context.settings.args.flatten_dictionaries() {
+        trie.inner.push(context.flattened(&tries)?);
+        trie.inner.extend(override_tries);
+        trie.inner.push(context.custom_trie(source_code)?);
+        return Ok(trie);
+    }
+    // Missing dictionaries were reported by `load_dictionaries`
+    for name in tries {
+        if let Some(trie_instance) = context.dictionaries.get(&name) {
+            trie.inner.push(trie_instance.clone());
+        }
+    }
+    trie.inner.extend(override_tries);
+    trie.inner.push(context.custom_trie(source_code)?);
+    Ok(trie)
+}
+
+/// Check a single file, using `source` instead of its content if given.
+async fn check_file(
+    context: &Arc<SharedRuntimeContext>,
+    file: &PathBuf,
+    source: Option<String>,
+) -> anyhow::Result<Vec<Typo>> {
+    let language_map = &context.settings.settings.language_map;
+    let (source_code, parser) = match source {
+        Some(source) => (source, get_parser_for_path(file, language_map)?),
+        None => get_code(file, language_map).await.context(format!(
+            "Failed to get code or parser for file: {}",
+            file.display()
+        ))?,
+    };
+    let Some(results) = context.results.get() else {
+        return check_code(context, file, source_code, parser);
+    };
+    let content_hash = ResultCache::hash(&source_code);
+    if let Some(typos) = results.get(file, &content_hash) {
+        return Ok(typos);
+    }
+    let typos = check_code(context, file, source_code, parser)?;
+    results.insert(file, content_hash, &typos);
+    Ok(typos)
+}
+
+/// Check the code of a file, parsed with `parser` if it has a grammar.
+fn check_code(
+    context: &Arc<SharedRuntimeContext>,
+    file: &PathBuf,
+    source_code: String,
+    parser: Option<tree_sitter::Parser>,
+) -> anyhow::Result<Vec<Typo>> {
+    let language_map = &context.settings.settings.language_map;
+    let dict = get_multi_trie(
+        context.get_file_dictionaries(file),
+        Some(file),
+        Some(&source_code),
+        context.clone(),
+    )
+    .context(format!(
+        "Failed to load dictionary set for file: {}",
+        file.display()
+    ))?;
+    if let Some("po" | "pot") = filesystem::get_file_extension(file).as_deref() {
+        let msgids: Arc<str> = gettext::extract(&source_code, gettext::Field::Msgid).into();
+        let mut typos = handle_text(&dict, &msgids);
+        let translation_dictionaries = context.settings.settings.translation_dictionaries.clone();
+        if !translation_dictionaries.is_empty() {
+            let dict = get_multi_trie(
+                translation_dictionaries,
+                Some(file),
+                Some(&source_code),
+                context.clone(),
+            )
+            .context(format!(
+                "Failed to load translation dictionaries for file: {}",
+                file.display()
+            ))?;
+            let msgstrs: Arc<str> = gettext::extract(&source_code, gettext::Field::Msgstr).into();
+            typos.append(&mut handle_text(&dict, &msgstrs));
+        }
+        return Ok(typos);
+    }
+    if filesystem::get_file_extension(file).as_deref() == Some("ipynb") {
+        let notebook = Notebook::parse(&source_code)
+            .context(format!("Failed to read notebook: {}", file.display()))?;
+        let mut typos = Vec::new();
+        for (index, cell) in notebook.cells.iter().enumerate() {
+            let language = notebook.cell_language(cell);
+            let parser = match language {
+                Some(language) => get_parser(language)?,
+                None => None,
+            };
+            let source_code: Arc<str> = cell.source.as_str().into();
+            for mut typo in check_source(&dict, &source_code, parser, language, &context.settings) {
+                typo.cell = Some(index + 1);
+                typos.push(typo);
+            }
+        }
+        return Ok(typos);
+    }
+    let language = detect_language(file, language_map);
+    let source_code: Arc<str> = source_code.into();
+    Ok(check_source(
+        &dict,
+        &source_code,
+        parser,
+        language.as_deref(),
+        &context.settings,
+    ))
+}
+
+/// Check source code of `language` with `parser`, or word by word without
+/// one.
+fn check_source(
+    dict: &MultiTrie,
+    source_code: &Arc<str>,
+    parser: Option<tree_sitter::Parser>,
+    language: Option<&str>,
+    settings: &MergedSettings,
+) -> Vec<Typo> {
+    let leetspeak = settings.args.leetspeak();
+    // Parsing fails if it's cancelled or the grammar misbehaves, and then
+    // the source is checked word by word
+    let tree = parser.and_then(|mut parser| parser.parse(source_code.as_bytes(), None));
+    if let Some(tree) = tree {
+        let root_node = Box::new(tree.root_node());
+        let language = language.unwrap_or_default();
+        let skip = settings.skipped_kinds(language);
+        let mut typos = match settings.prose_kinds(language) {
+            Some(kinds) => handle_prose(dict, &root_node, source_code, &kinds, &skip),
+            None => handle_node(dict, &root_node, source_code, &skip),
+        };
+        if leetspeak {
+            typos.append(&mut handle_leetspeak(dict, &root_node, source_code));
+        }
+        typos
+    } else {
+        let mut typos = handle_text(dict, source_code);
+        if leetspeak {
+            typos.append(&mut handle_leetspeak_text(dict, source_code));
+        }
+        typos
+    }
+}
+
+#[tokio::main]
+async fn handle_file(
+    context: Arc<SharedRuntimeContext>,
+    file_receiver: Arc<Mutex<tokio::sync::mpsc::Receiver<FileToCheck>>>,
+    result_sender: tokio::sync::mpsc::Sender<CheckFileResult>,
+) -> anyhow::Result<()> {
+    if context.settings.verbose() {
+        println!("Starting thread #{:?}", thread::current().id());
+    }
+    while !context.cancelled.load(Ordering::Relaxed) {
+        let FileToCheck { path: file, source } =
+            if let Some(f) = file_receiver.lock().await.recv().await {
+                f
+            } else {
+                break;
+            };
+        let typos = check_file(&context, &file, source).await;
+        let result = CheckFileResult {
+            file: file.clone(),
+            typos,
+        };
+        let sent = result_sender.send(result).await;
+        if context.cancelled.load(Ordering::Relaxed) {
+            // Results are no longer received
+            break;
+        }
+        sent.context(format!(
+            "Failed to send result for file: {}",
+            file.display()
+        ))?;
+    }
+    if context.settings.verbose() {
+        println!("Finalizing thread #{:?}", thread::current().id());
+    }
+    Ok(())
+}
+
+async fn load_dictionaries(context: Arc<SharedRuntimeContext>) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let c = context.get_dictionaries();
+    let base_dictionaries = context.get_base_dictionaries();
+    let translation_dictionaries = &context.settings.settings.translation_dictionaries;
+    let language_dictionaries = context
+        .settings
+        .settings
+        .language_settings
+        .keys()
+        .flat_map(|language| context.settings.language_dictionaries(language))
+        .collect::<Vec<_>>();
+    let override_dictionaries = context
+        .settings
+        .settings
+        .overrides
+        .iter()
+        .flat_map(|entry| entry.dictionaries.iter().cloned())
+        .collect::<Vec<_>>();
+    // Each compilation holds a permit, so at most this many tries are built
+    // at once
+    let permits = context
+        .settings
+        .args
+        .dictionary_load_concurrency()
+        .map_or(Semaphore::MAX_PERMITS, NonZeroUsize::get);
+    let semaphore = Arc::new(Semaphore::new(permits));
+    let mut compilations = JoinSet::new();
+    let git_options = context.settings.settings.git_options();
+    for dict in c {
+        let names = dict.get_names()?;
+        if !base_dictionaries
+            .iter()
+            .chain(translation_dictionaries)
+            .chain(&language_dictionaries)
+            .chain(&override_dictionaries)
+            .any(|x| names.contains(x))
+        {
+            // Don't load pointless tries
+            continue;
+        }
+        if let Some(globs) = dict.get_globs()? {
+            for name in &names {
+                context.scopes.insert(name.clone(), globs.clone());
+            }
+        }
+        let permit = semaphore.clone().acquire_owned().await?;
+        compilations.spawn_blocking(move || {
+            let _permit = permit;
+            let start = Instant::now();
+            let trie = dict.init_git(git_options).and_then(|()| dict.compile());
+            (names, trie, start.elapsed())
+        });
+    }
+    context.load_scopes()?;
+    context.load_overrides()?;
+    context.load_patterns()?;
+    context.load_custom_rules()?;
+    let count = compilations.len();
+    let mut sequential = Duration::ZERO;
+    while let Some(res) = compilations.join_next().await {
+        let (names, trie, elapsed) = res?;
+        sequential += elapsed;
+        let trie = match trie {
+            Ok(trie) => Arc::new(trie),
+            Err(e) => {
+                // Keep checking without it, as if the dictionary were empty
+                eprintln!("Skipping dictionary {}: {e:#}", names.join(", "));
+                Arc::new(Trie::from(&[] as &[Rule]))
+            }
+        };
+        for name in names {
+            // TODO: handle overwrites
+            context.dictionaries.insert(name, trie.clone());
+        }
+    }
+    for name in base_dictionaries
+        .iter()
+        .chain(translation_dictionaries)
+        .chain(&override_dictionaries)
+    {
+        if !context.dictionaries.contains_key(name) {
+            eprintln!("Dictionary {name} not found, checking without it");
+        }
+    }
+    if context.settings.verbose() {
+        let elapsed = start.elapsed();
+        println!(
+            "Compiled {count} dictionaries in {elapsed:.2?} ({sequential:.2?} of compile time, \
+             {:.1}x speedup)",
+            sequential.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON)
+        );
+    }
+    if context.settings.args.flatten_dictionaries() {
+        // Build the set used by files outside every scope up front; scoped
+        // sets are built when first needed
+        let start = Instant::now();
+        let mut unscoped = base_dictionaries;
+        unscoped.retain(|name| !context.scopes.contains_key(name));
+        context.flattened(&unscoped)?;
+        if context.settings.verbose() {
+            println!("Flattened dictionaries in {:.2?}", start.elapsed());
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` matches one of `patterns`.
+fn matches_any(patterns: &[glob::Pattern], path: &Path) -> bool {
+    patterns.iter().any(|pattern| pattern.matches_path(path))
+}
+
+/// Check a file, or the files in a folder that match the globs of
+/// `settings`, one at a time. Ignore files are respected like in the `check`
+/// command, and a folder's project words file is used.
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use cargo_csc::{Settings, check_path};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for result in check_path(Settings::new(), Path::new("src")).await? {
+///     for typo in result.typos? {
+///         println!("{}:{}: {}", result.file.display(), typo.line, typo.word);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn check_path(settings: Settings, path: &Path) -> anyhow::Result<Vec<CheckFileResult>> {
+    if !path.exists() {
+        bail!("Path not found: {}", path.display());
+    }
+    let files = if path.is_dir() {
+        let patterns = settings.glob_patterns(None)?;
+        ignore::WalkBuilder::new(path)
+            .build()
+            .flatten()
+            .map(ignore::DirEntry::into_path)
+            .filter(|path| path.is_file() && matches_any(&patterns, path))
+            .collect::<Vec<_>>()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    let dir = if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        PathBuf::from(".")
+    };
+    let context = Arc::new(SharedRuntimeContext::new(MergedSettings::new(
+        Box::new(LibraryArgs { dir }),
+        settings,
+    )));
+    load_dictionaries(context.clone()).await?;
+    let mut results = Vec::new();
+    for file in files {
+        let typos = check_file(&context, &file, None).await;
+        results.push(CheckFileResult { file, typos });
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::LspArgs;
+
+    #[test]
+    fn test_check_source_without_tree() {
+        let words = Dictionary::new_from_strings(&["hello".to_string(), "world".to_string()]);
+        let mut dict = MultiTrie::new();
+        dict.inner.push(Arc::new(words.compile().unwrap()));
+        let settings = MergedSettings::new(Box::new(LspArgs { settings: None }), Settings::new());
+        // A parser without a language never produces a tree
+        let parser = tree_sitter::Parser::new();
+        let source_code: Arc<str> = "hello wrold".into();
+        let typos = check_source(&dict, &source_code, Some(parser), Some("rust"), &settings);
+        assert_eq!(typos.len(), 1);
+        assert_eq!(typos[0].word, "wrold");
+    }
+
+    #[tokio::test]
+    async fn test_check_path_project_words() {
+        // The tests run in the crate root, away from the checked folder
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hello wrold tpyo\n").unwrap();
+        std::fs::write(dir.path().join("code-spellcheck.words.txt"), "wrold\n").unwrap();
+        let mut settings = Settings::new();
+        settings.dictionaries.clear();
+        settings.words = vec!["hello".to_string()];
+        let results = check_path(settings, dir.path()).await.unwrap();
+        let words = results
+            .into_iter()
+            .filter(|result| result.file.ends_with("a.txt"))
+            .flat_map(|result| result.typos.unwrap())
+            .map(|typo| typo.word)
+            .collect::<Vec<_>>();
+        assert_eq!(words, ["tpyo"]);
+    }
+
+    #[test]
+    #[cfg(feature = "lsp")]
+    fn test_add_project_word() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = MergedSettings::new(
+            Box::new(LspArgs {
+                settings: Some(dir.path().join("code-spellcheck.json")),
+            }),
+            Settings::new(),
+        );
+        let path = settings.project_words_path();
+        settings.add_project_word("wrold").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "wrold\n");
+        std::fs::write(&path, "wrold").unwrap();
+        settings.add_project_word("helo").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "wrold\nhelo\n");
+    }
+}
@@ -1,16 +1,129 @@
-use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::*;
-use tower_lsp::{Client, LanguageServer, LspService, Server};
+use std::{path::PathBuf, sync::Arc};
+
+use dashmap::{DashMap, DashSet};
+use tower_lsp::{
+    Client, LanguageServer, LspService, Server,
+    jsonrpc::{Error, Result},
+    lsp_types::*,
+};
+
+use crate::{SharedRuntimeContext, Typo, TypoCategory};
+
+/// Source of the published diagnostics, used to recognize them in code
+/// action requests.
+const SOURCE: &str = "csc";
+
+/// Command adding its argument to the project words file.
+const ADD_WORD_COMMAND: &str = "csc.addWord";
+
+/// Position of a byte offset, with the column counted in UTF-16 code units
+/// as LSP expects.
+fn position(source: &str, offset: usize) -> Position {
+    let before = source.get(..offset).unwrap_or(source);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+/// The range of a typo in `source`, the text it was found in.
+fn typo_range(source: &str, typo: &Typo) -> Range {
+    let range = typo.byte_range();
+    Range::new(position(source, range.start), position(source, range.end))
+}
+
+fn diagnostic(source: &str, typo: &Typo) -> Diagnostic {
+    let kind = match typo.category {
+        TypoCategory::Unknown => "Unknown",
+        TypoCategory::Forbidden => "Forbidden",
+    };
+    let message = match typo.suggestion {
+        Some(ref suggestion) => {
+            format!("{kind} word `{}`. Did you mean `{suggestion}`?", typo.word)
+        }
+        None => format!("{kind} word `{}`.", typo.word),
+    };
+    let severity = match typo.category {
+        TypoCategory::Unknown => DiagnosticSeverity::WARNING,
+        TypoCategory::Forbidden => DiagnosticSeverity::ERROR,
+    };
+    Diagnostic {
+        range: typo_range(source, typo),
+        severity: Some(severity),
+        source: Some(SOURCE.to_string()),
+        message,
+        // Kept for the code actions
+        data: Some(serde_json::json!({
+            "word": typo.word,
+            "suggestion": typo.suggestion,
+        })),
+        ..Default::default()
+    }
+}
 
-#[derive(Debug)]
 struct Backend {
     client: Client,
+    context: Arc<SharedRuntimeContext>,
+    /// Text of the open documents
+    documents: DashMap<Url, String>,
+    /// Words added to the project words file since the server started, in
+    /// lowercase, which the loaded allowlist doesn't know yet
+    added: DashSet<String>,
+}
+
+impl Backend {
+    /// Check a document and publish its typos.
+    async fn publish(&self, uri: Url, version: Option<i32>) {
+        let Some(text) = self.documents.get(&uri).map(|text| text.clone()) else {
+            return;
+        };
+        // Unsaved documents are still checked by their extension
+        let path = uri
+            .to_file_path()
+            .unwrap_or_else(|()| PathBuf::from(uri.path()));
+        let typos = match crate::check_file(&self.context, &path, Some(text.clone())).await {
+            Ok(typos) => typos,
+            Err(e) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("Failed to check {uri}: {e:#}"))
+                    .await;
+                return;
+            }
+        };
+        let diagnostics = typos
+            .iter()
+            // Notebook cells can't be mapped back to the document
+            .filter(|typo| typo.cell.is_none())
+            .filter(|typo| !self.added.contains(&typo.word.to_lowercase()))
+            .map(|typo| diagnostic(&text, typo))
+            .collect();
+        self.client
+            .publish_diagnostics(uri, diagnostics, version)
+            .await;
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
-        Ok(InitializeResult::default())
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::FULL,
+                )),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![ADD_WORD_COMMAND.to_string()],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            server_info: Some(ServerInfo {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
+        })
     }
 
     async fn initialized(&self, _: InitializedParams) {
@@ -22,12 +135,140 @@ impl LanguageServer for Backend {
     async fn shutdown(&self) -> Result<()> {
         Ok(())
     }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let document = params.text_document;
+        self.documents.insert(document.uri.clone(), document.text);
+        self.publish(document.uri, Some(document.version)).await;
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let document = params.text_document;
+        // Full sync, so the last change holds the whole text
+        let Some(change) = params.content_changes.into_iter().last() else {
+            return;
+        };
+        self.documents.insert(document.uri.clone(), change.text);
+        self.publish(document.uri, Some(document.version)).await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.remove(&uri);
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let mut actions = Vec::new();
+        for diagnostic in params.context.diagnostics {
+            if diagnostic.source.as_deref() != Some(SOURCE) {
+                continue;
+            }
+            let Some(data) = diagnostic.data.clone() else {
+                continue;
+            };
+            if let Some(suggestion) = data["suggestion"].as_str() {
+                let edit = TextEdit::new(diagnostic.range, suggestion.to_string());
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Replace with `{suggestion}`"),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some([(uri.clone(), vec![edit])].into_iter().collect()),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                }));
+            }
+            if let Some(word) = data["word"].as_str() {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Add `{word}` to project words"),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    command: Some(Command::new(
+                        format!("Add `{word}` to project words"),
+                        ADD_WORD_COMMAND.to_string(),
+                        Some(vec![serde_json::Value::String(word.to_string())]),
+                    )),
+                    ..Default::default()
+                }));
+            }
+        }
+        Ok(Some(actions))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        if params.command != ADD_WORD_COMMAND {
+            return Err(Error::invalid_params(format!(
+                "Unknown command: {}",
+                params.command
+            )));
+        }
+        let Some(word) = params.arguments.first().and_then(serde_json::Value::as_str) else {
+            return Err(Error::invalid_params("Expected the word to add"));
+        };
+        if let Err(e) = self.context.settings.add_project_word(word) {
+            self.client
+                .show_message(MessageType::ERROR, format!("{e:#}"))
+                .await;
+            return Ok(None);
+        }
+        self.added.insert(word.to_lowercase());
+        // Re-check the open documents without the word
+        let uris = self
+            .documents
+            .iter()
+            .map(|document| document.key().clone())
+            .collect::<Vec<_>>();
+        for uri in uris {
+            self.publish(uri, None).await;
+        }
+        Ok(None)
+    }
 }
 
-pub async fn lsp() {
+/// Serve LSP over stdio, checking documents with the dictionaries already
+/// loaded into `context`.
+pub async fn lsp(context: Arc<SharedRuntimeContext>) {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| Backend { client });
+    let (service, socket) = LspService::new(|client| Backend {
+        client,
+        context,
+        documents: DashMap::new(),
+        added: DashSet::new(),
+    });
     Server::new(stdin, stdout, socket).serve(service).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typo_range() {
+        let source = "// héllo\nlet x = \"a wrold\";\n";
+        let typo = Typo {
+            line: 2,
+            column: 12,
+            length: 5,
+            word: "wrold".to_string(),
+            suggestion: Some("world".to_string()),
+            category: TypoCategory::Unknown,
+            cell: None,
+            node_kind: None,
+            source: source.into(),
+        };
+        assert_eq!(
+            typo_range(source, &typo),
+            Range::new(Position::new(1, 11), Position::new(1, 16))
+        );
+        assert_eq!(position(source, 6), Position::new(0, 5));
+    }
+}
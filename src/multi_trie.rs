@@ -1,11 +1,121 @@
 use std::{cell::OnceCell, sync::Arc};
 
-use crate::Trie;
+use dashmap::{DashMap, DashSet};
+use regex::Regex;
+
+use crate::{Trie, TypoCategory, dictionary::Rule};
+
+/// Similarity from 0 to 1 that a suggestion must exceed by default.
+pub const DEFAULT_SUGGESTION_THRESHOLD: f64 = 0.7;
+
+/// Suggestions shared between threads, keyed on the misspelled word. Once
+/// `capacity` words are cached, further suggestions are computed but not kept.
+#[derive(Debug)]
+pub struct SuggestionCache {
+    entries: DashMap<String, Option<String>>,
+    capacity: usize,
+}
+
+impl SuggestionCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: DashMap::new(),
+            capacity,
+        }
+    }
+
+    fn get_or_insert_with<F>(&self, word: &str, f: F) -> Option<String>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        if let Some(suggestion) = self.entries.get(word) {
+            return suggestion.clone();
+        }
+        let suggestion = f();
+        if self.entries.len() < self.capacity {
+            self.entries.insert(word.to_string(), suggestion.clone());
+        }
+        suggestion
+    }
+}
+
+/// Tracks which words of some named tries are looked up while checking, to
+/// find allowlist entries that are never needed.
+#[derive(Debug, Default)]
+pub struct WordUsage {
+    tries: Vec<(String, Arc<Trie>)>,
+    used: DashSet<String>,
+}
+
+impl WordUsage {
+    pub fn new(tries: Vec<(String, Arc<Trie>)>) -> Self {
+        Self {
+            tries,
+            used: DashSet::new(),
+        }
+    }
+
+    fn record(&self, word: &str) {
+        if !self.used.contains(word) && self.tries.iter().any(|(_, trie)| trie.contains(word)) {
+            self.used.insert(word.to_string());
+        }
+    }
+
+    /// The allowed words of each trie that were never looked up, by trie
+    /// name. Tries with no unused words are left out.
+    pub fn unused(&self) -> Vec<(String, Vec<String>)> {
+        self.tries
+            .iter()
+            .map(|(name, trie)| {
+                let words = trie
+                    .to_rules()
+                    .into_iter()
+                    .filter_map(|rule| match rule {
+                        Rule::Allow(word) | Rule::AllowWithFrequency(word, _) => Some(word),
+                        _ => None,
+                    })
+                    .filter(|word| !self.used.contains(word))
+                    .collect::<Vec<_>>();
+                (name.clone(), words)
+            })
+            .filter(|(_, words)| !words.is_empty())
+            .collect()
+    }
+}
+
+/// Split `word` where a lowercase letter is followed by an uppercase one, as
+/// in `userName` -> `user`, `Name`. Runs of capitals like `KUBE4` stay whole.
+fn split_camel_case(word: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    for (i, c) in word.char_indices() {
+        if previous.is_some_and(char::is_lowercase) && c.is_uppercase() {
+            parts.push(&word[start..i]);
+            start = i;
+        }
+        previous = Some(c);
+    }
+    parts.push(&word[start..]);
+    parts
+}
 
 #[derive(Debug, Default)]
 pub struct MultiTrie {
     pub inner: Vec<Arc<Trie>>,
     pub all_words: OnceCell<Vec<String>>,
+    /// Prefixes stripped from identifiers before checking, e.g. `pb_`
+    pub strip_prefixes: Vec<String>,
+    /// Tokens matching any of these are never reported
+    pub patterns: Vec<Regex>,
+    pub suggestion_cache: Option<Arc<SuggestionCache>>,
+    /// Words that dictionaries list as used less often than this are unknown
+    pub min_frequency: Option<u64>,
+    /// Similarity a suggestion must exceed, [`DEFAULT_SUGGESTION_THRESHOLD`]
+    /// if not set
+    pub suggestion_threshold: Option<f64>,
+    /// Records the words looked up, for `--unused-words`
+    pub usage: Option<Arc<WordUsage>>,
 }
 
 impl MultiTrie {
@@ -13,18 +123,54 @@ impl MultiTrie {
         MultiTrie {
             inner: Vec::new(),
             all_words: OnceCell::new(),
+            strip_prefixes: Vec::new(),
+            patterns: Vec::new(),
+            suggestion_cache: None,
+            min_frequency: None,
+            suggestion_threshold: None,
+            usage: None,
         }
     }
 
+    /// Whether `word` is known, which it isn't if any of the tries forbids
+    /// it.
     pub fn contains(&self, word: &str) -> bool {
+        if let Some(ref usage) = self.usage {
+            usage.record(word);
+        }
+        if self.is_forbidden(word) {
+            return false;
+        }
         for trie in &self.inner {
-            if trie.contains(word) {
+            if !trie.contains(word) {
+                continue;
+            }
+            // Words without a frequency are always known
+            let too_rare = self
+                .min_frequency
+                .zip(trie.frequency(word))
+                .is_some_and(|(min, frequency)| frequency < min);
+            if !too_rare {
                 return true;
             }
         }
         false
     }
 
+    /// Whether any of the tries forbids `word`.
+    pub fn is_forbidden(&self, word: &str) -> bool {
+        self.inner.iter().any(|trie| trie.is_disallowed(word))
+    }
+
+    /// The category of a word reported as a typo.
+    pub fn category(&self, word: &str) -> TypoCategory {
+        if self.is_forbidden(&word.to_lowercase()) {
+            TypoCategory::Forbidden
+        } else {
+            TypoCategory::Unknown
+        }
+    }
+
     fn check_parts(&self, parts: &[&str]) -> Option<String> {
         fn split_by_capitalization(word: &str) -> Vec<String> {
             let mut parts = Vec::new();
@@ -43,11 +189,14 @@ impl MultiTrie {
         }
 
         for &part in parts {
-            if !self.contains(&part.to_ascii_lowercase()) {
+            if self.is_allowed_pattern(part) {
+                continue;
+            }
+            if !self.contains(&part.to_lowercase()) {
                 // check if part is fully numeric
                 if !part.chars().all(char::is_numeric) {
                     for sub_part in split_by_capitalization(part) {
-                        if !self.contains(&sub_part.to_ascii_lowercase()) {
+                        if !self.contains(&sub_part.to_lowercase()) {
                             return Some(part.to_string());
                         }
                     }
@@ -57,37 +206,253 @@ impl MultiTrie {
         None
     }
 
+    fn is_allowed_pattern(&self, token: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.is_match(token))
+    }
+
     pub fn handle_identifier(&self, word: &str) -> Option<String> {
+        if self.is_allowed_pattern(word) {
+            return None;
+        }
         let splitters = [
             ' ', '_', '-', '(', ')', '{', '}', '[', ']', ',', '.', ';', ':', '?', '!', '"', '\'',
             '&', '/', '|', '<', '>', '=', '+', '-', '*', '%', '^', '~', '`', '@', '#', '$', '!',
-            '?', ':', ';', '(', ')', '{', '}', '[', ']', ',', '.', '/', '1', '2', '3', '4', '5',
-            '6', '7', '8', '9', '0', '\\',
+            '?', ':', ';', '(', ')', '{', '}', '[', ']', ',', '.', '/', '\\',
         ];
+        let word = self
+            .strip_prefixes
+            .iter()
+            .find_map(|prefix| word.strip_prefix(prefix.as_str()))
+            .unwrap_or(word);
         // TODO: handle \ properly
-        let parts = word
-            .split(|c| splitters.contains(&c))
-            .filter(|part| part.len() > 3)
-            .collect::<Vec<_>>();
+        let mut parts = Vec::new();
+        let mut segments = Vec::new();
+        for segment in word.split(|c| splitters.contains(&c)) {
+            // Patterns also apply to the parts of compound identifiers, like
+            // `KUBE4` in `KUBE4_client` and `userKUBE4`
+            let pieces = split_camel_case(segment);
+            if pieces.iter().any(|piece| self.is_allowed_pattern(piece)) {
+                segments.extend(
+                    pieces
+                        .into_iter()
+                        .filter(|piece| !self.is_allowed_pattern(piece)),
+                );
+            } else {
+                segments.push(segment);
+            }
+        }
+        for segment in segments {
+            let runs = segment
+                .split(|c: char| c.is_ascii_digit())
+                .collect::<Vec<_>>();
+            // Allowlisted tokens like `log4j` are known as a whole
+            if runs.len() > 1 && self.contains(&segment.to_lowercase()) {
+                continue;
+            }
+            parts.extend(runs);
+        }
+        // Short runs aren't checked, which covers the abbreviations joined by
+        // digits in `utf8mb4` and `base64url` while words after the digits,
+        // as in `base64encoding`, still are
+        parts.retain(|part| part.len() > 3);
         self.check_parts(&parts)
     }
 
+    /// Find a token in `word` that is only a known word once de-leetspeaked
+    /// (`passw0rd` -> `password`), returning the token and the known word.
+    pub fn handle_leetspeak(&self, word: &str) -> Option<(String, String)> {
+        fn deleet(c: char) -> char {
+            match c {
+                '3' => 'e',
+                '0' => 'o',
+                '1' => 'l',
+                _ => c,
+            }
+        }
+
+        for token in word.split(|c: char| !c.is_ascii_alphanumeric()) {
+            if token.len() <= 3
+                || !token.chars().any(|c| c.is_ascii_alphabetic())
+                || !token.chars().any(|c| deleet(c) != c)
+            {
+                continue;
+            }
+            let token = token.to_lowercase();
+            let plain = token.chars().map(deleet).collect::<String>();
+            if self.contains(&plain) && !self.contains(&token) {
+                return Some((token, plain));
+            }
+        }
+        None
+    }
+
     pub fn suggestion(&self, word: &str) -> Option<String> {
-        const THRESHOLD: f64 = 0.7;
+        let word = word.to_lowercase();
+        // Forbidden words are known, but not as a replacement
+        if self.is_forbidden(&word) {
+            return None;
+        }
+        match self.suggestion_cache {
+            Some(ref cache) => cache.get_or_insert_with(&word, || self.find_suggestion(&word)),
+            None => self.find_suggestion(&word),
+        }
+    }
 
+    fn find_suggestion(&self, word: &str) -> Option<String> {
         let (score, best_suggestion) = self
             .inner
             .iter()
-            .filter_map(|t| t.check(word).unwrap())
-            .filter_map(|suggestion| {
+            // Words too long for a Levenshtein automaton get no suggestion
+            .filter_map(|t| t.check(word).ok().flatten())
+            .map(|suggestion| {
                 let score = strsim::normalized_damerau_levenshtein(word, &suggestion);
-                Some((score, suggestion))
+                (score, suggestion)
             })
-            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))?;
-        if score > THRESHOLD {
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))?;
+        let threshold = self
+            .suggestion_threshold
+            .unwrap_or(DEFAULT_SUGGESTION_THRESHOLD);
+        if score > threshold {
             Some(best_suggestion)
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dictionary;
+
+    fn multi_trie(words: &[&str]) -> MultiTrie {
+        let words = words.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let mut trie = MultiTrie::new();
+        trie.inner.push(Arc::new(
+            Dictionary::new_from_strings(&words).compile().unwrap(),
+        ));
+        trie
+    }
+
+    #[test]
+    fn test_word_usage() {
+        let mut trie = multi_trie(&["user", "name"]);
+        let words = Dictionary::new_from_strings(&["name".to_string(), "stale".to_string()])
+            .compile()
+            .unwrap();
+        let usage = Arc::new(WordUsage::new(vec![("words".to_string(), Arc::new(words))]));
+        trie.usage = Some(usage.clone());
+        assert_eq!(trie.handle_identifier("userName"), None);
+        assert_eq!(
+            usage.unused(),
+            [("words".to_string(), vec!["stale".to_string()])]
+        );
+    }
+
+    #[test]
+    fn test_strip_prefixes() {
+        let mut trie = multi_trie(&["user", "name"]);
+        assert_eq!(
+            trie.handle_identifier("pbxx_userName"),
+            Some("pbxx".to_string())
+        );
+        trie.strip_prefixes = vec!["pbxx_".to_string()];
+        assert_eq!(trie.handle_identifier("pbxx_userName"), None);
+        assert_eq!(
+            trie.handle_identifier("pbxx_userNmae"),
+            Some("userNmae".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unicode_case() {
+        let trie = multi_trie(&["ÉCOLE", "straße"]);
+        assert_eq!(trie.handle_identifier("école"), None);
+        assert_eq!(trie.handle_identifier("ÉCOLE"), None);
+        assert_eq!(trie.handle_identifier("Straße"), None);
+        assert_eq!(trie.handle_identifier("ÉCOLES"), Some("ÉCOLES".to_string()));
+    }
+
+    #[test]
+    fn test_patterns() {
+        let mut trie = multi_trie(&["user"]);
+        assert_eq!(trie.handle_identifier("KUBE4"), Some("KUBE".to_string()));
+        trie.patterns = vec![Regex::new(r"^[A-Z]{2,5}\d+$").unwrap()];
+        assert_eq!(trie.handle_identifier("AWS4"), None);
+        assert_eq!(trie.handle_identifier("KUBE4"), None);
+        assert_eq!(trie.handle_identifier("KUBE4_user"), None);
+        assert_eq!(trie.handle_identifier("userKUBE4"), None);
+        assert_eq!(
+            trie.handle_identifier("KUBE4_usre"),
+            Some("usre".to_string())
+        );
+    }
+
+    #[test]
+    fn test_digit_joined_words() {
+        let trie = multi_trie(&["base", "python", "string", "sha", "utf", "log"]);
+        for word in ["log4j", "utf8mb4", "sha512", "python3", "base64url"] {
+            assert_eq!(trie.handle_identifier(word), None, "{word}");
+        }
+        assert_eq!(
+            trie.handle_identifier("base64encodng"),
+            Some("encodng".to_string())
+        );
+        assert_eq!(
+            trie.handle_identifier("bsae64url"),
+            Some("bsae".to_string())
+        );
+        assert_eq!(
+            trie.handle_identifier("x86_strng"),
+            Some("strng".to_string())
+        );
+        assert_eq!(trie.handle_identifier("64strng"), Some("strng".to_string()));
+        let trie = multi_trie(&["pkcs12keystore"]);
+        assert_eq!(trie.handle_identifier("pkcs12keystore"), None);
+    }
+
+    #[test]
+    fn test_strip_short_prefix() {
+        let mut trie = multi_trie(&["user", "name"]);
+        trie.strip_prefixes = vec!["pb_".to_string()];
+        assert_eq!(trie.handle_identifier("pb_userName"), None);
+        assert_eq!(
+            trie.handle_identifier("pb_userNaem"),
+            Some("userNaem".to_string())
+        );
+    }
+
+    #[test]
+    fn test_min_frequency() {
+        let mut trie = multi_trie(&["common\t100", "obscure\t2", "plain"]);
+        assert!(trie.contains("obscure"));
+        trie.min_frequency = Some(10);
+        assert!(trie.contains("common"));
+        assert!(!trie.contains("obscure"));
+        assert!(trie.contains("plain"));
+    }
+
+    #[test]
+    fn test_suggestion_picks_closest() {
+        let mut trie = multi_trie(&["spewing"]);
+        trie.inner
+            .extend(multi_trie(&["spelling"]).inner.iter().cloned());
+        // Both are one edit away, but `spelling` is more similar
+        assert_eq!(trie.suggestion("speling"), Some("spelling".to_string()));
+    }
+
+    #[test]
+    fn test_suggestion_cache() {
+        let cache = Arc::new(SuggestionCache::new(1));
+        let mut trie = multi_trie(&["spelling", "world"]);
+        trie.suggestion_cache = Some(cache.clone());
+        assert_eq!(trie.suggestion("Speling"), Some("spelling".to_string()));
+        assert_eq!(trie.suggestion("wurld"), Some("world".to_string()));
+        // Only the first word fits in the cache
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(
+            cache.entries.get("speling").map(|entry| entry.clone()),
+            Some(Some("spelling".to_string()))
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Context;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CellSource {
+    Lines(Vec<String>),
+    Text(String),
+}
+
+impl Default for CellSource {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawCell {
+    cell_type: String,
+    #[serde(default)]
+    source: CellSource,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KernelSpec {
+    language: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LanguageInfo {
+    name: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Metadata {
+    #[serde(default)]
+    kernelspec: KernelSpec,
+    #[serde(default)]
+    language_info: LanguageInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawNotebook {
+    #[serde(default)]
+    cells: Vec<RawCell>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellKind {
+    Markdown,
+    Code,
+    Raw,
+}
+
+#[derive(Debug)]
+pub struct Cell {
+    pub kind: CellKind,
+    pub source: String,
+}
+
+/// The cells of a Jupyter notebook (`.ipynb`).
+#[derive(Debug)]
+pub struct Notebook {
+    /// Language of the code cells, as declared by the kernel
+    pub language: Option<String>,
+    pub cells: Vec<Cell>,
+}
+
+impl Notebook {
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let notebook: RawNotebook =
+            serde_json::from_str(content).context("Failed to parse notebook")?;
+        let language = notebook
+            .metadata
+            .kernelspec
+            .language
+            .or(notebook.metadata.language_info.name)
+            .map(|language| language.to_ascii_lowercase());
+        let cells = notebook
+            .cells
+            .into_iter()
+            .map(|cell| Cell {
+                kind: match cell.cell_type.as_str() {
+                    "markdown" => CellKind::Markdown,
+                    "code" => CellKind::Code,
+                    _ => CellKind::Raw,
+                },
+                source: match cell.source {
+                    CellSource::Lines(lines) => lines.concat(),
+                    CellSource::Text(text) => text,
+                },
+            })
+            .collect();
+        Ok(Self { language, cells })
+    }
+
+    /// The grammar used to check a cell, if any.
+    pub fn cell_language(&self, cell: &Cell) -> Option<&str> {
+        match cell.kind {
+            CellKind::Markdown => Some("markdown"),
+            CellKind::Code => self.language.as_deref(),
+            CellKind::Raw => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_notebook() {
+        let notebook = Notebook::parse(
+            r##"{
+                "cells": [
+                    {"cell_type": "markdown", "metadata": {}, "source": ["# Title\n", "Some text"]},
+                    {"cell_type": "code", "metadata": {}, "outputs": [], "source": "x = 1"},
+                    {"cell_type": "raw", "metadata": {}, "source": []}
+                ],
+                "metadata": {"kernelspec": {"language": "Python", "name": "python3"}},
+                "nbformat": 4,
+                "nbformat_minor": 5
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(notebook.language.as_deref(), Some("python"));
+        assert_eq!(notebook.cells.len(), 3);
+        assert_eq!(notebook.cells[0].source, "# Title\nSome text");
+        assert_eq!(notebook.cell_language(&notebook.cells[0]), Some("markdown"));
+        assert_eq!(notebook.cells[1].source, "x = 1");
+        assert_eq!(notebook.cell_language(&notebook.cells[1]), Some("python"));
+        assert_eq!(notebook.cells[2].kind, CellKind::Raw);
+        assert_eq!(notebook.cell_language(&notebook.cells[2]), None);
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use relative_path::PathExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{HashMap, Typo, TypoCategory, args::GroupBy};
+
+pub struct CheckFileResult {
+    pub file: PathBuf,
+    /// The file's typos, or why it couldn't be checked
+    pub typos: anyhow::Result<Vec<Typo>>,
+}
+
+/// A [`CheckFileResult`] as stored in a JSON Lines results file.
+#[derive(Serialize, Deserialize)]
+struct ResultRecord {
+    file: PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    typos: Vec<Typo>,
+    /// Only stored for files with typos, to render their diagnostics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl ResultRecord {
+    fn new(result: &CheckFileResult) -> Self {
+        match result.typos {
+            Ok(ref typos) => Self {
+                file: result.file.clone(),
+                typos: typos.clone(),
+                source: typos.first().map(|typo| typo.source.to_string()),
+                error: None,
+            },
+            Err(ref e) => Self {
+                file: result.file.clone(),
+                typos: Vec::new(),
+                source: None,
+                error: Some(format!("{e:#}")),
+            },
+        }
+    }
+
+    fn into_result(self) -> CheckFileResult {
+        let source: Arc<str> = self.source.unwrap_or_default().into();
+        let typos = match self.error {
+            Some(error) => Err(anyhow::anyhow!(error)),
+            None => Ok(self
+                .typos
+                .into_iter()
+                .map(|mut typo| {
+                    typo.source = source.clone();
+                    typo
+                })
+                .collect()),
+        };
+        CheckFileResult {
+            file: self.file,
+            typos,
+        }
+    }
+}
+
+/// Load the results stored in a JSON Lines results file.
+pub fn load_results(path: &PathBuf) -> anyhow::Result<Vec<CheckFileResult>> {
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read results: {}", path.display()))?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let record: ResultRecord = serde_json::from_str(line)
+                .context(format!("Invalid result in {}", path.display()))?;
+            Ok(record.into_result())
+        })
+        .collect()
+}
+
+/// Resolve `.` and `..` in an absolute path without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// How a check went, from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    /// Every file was checked and no typos were found
+    Clean,
+    /// Every file was checked, but some had typos
+    Typos,
+    /// Some files couldn't be checked
+    Error,
+}
+
+/// Prints the results of a check as they come in and keeps the totals.
+pub struct Reporter {
+    total_files: usize,
+    counter: usize,
+    progress: bool,
+    group_by: Option<GroupBy>,
+    occurrences: HashMap<String, Vec<(String, Typo)>>,
+    export: Option<fs::File>,
+    /// Directory that displayed paths are relative to
+    relative_to: Option<PathBuf>,
+    errors: usize,
+    /// Print typos on one line each instead of as diagnostics
+    short: bool,
+    /// Categories of typos that fail the check
+    error_on: Vec<TypoCategory>,
+    /// Typos that fail the check
+    typos: usize,
+}
+
+impl Reporter {
+    pub fn new(total_files: usize, progress: bool, group_by: Option<GroupBy>) -> Self {
+        Self {
+            total_files,
+            counter: 0,
+            progress,
+            group_by,
+            occurrences: HashMap::default(),
+            export: None,
+            relative_to: None,
+            errors: 0,
+            short: false,
+            error_on: vec![TypoCategory::Unknown, TypoCategory::Forbidden],
+            typos: 0,
+        }
+    }
+
+    /// Also write every result to `path` as JSON Lines.
+    pub fn export_to(&mut self, path: &PathBuf) -> anyhow::Result<()> {
+        let file = fs::File::create(path)
+            .context(format!("Failed to create results file: {}", path.display()))?;
+        self.export = Some(file);
+        Ok(())
+    }
+
+    /// Display paths relative to `base` instead of as they were found.
+    pub fn relative_to(&mut self, base: &Path) -> anyhow::Result<()> {
+        let base = std::path::absolute(base)
+            .context(format!("Failed to resolve path: {}", base.display()))?;
+        self.relative_to = Some(normalize(&base));
+        Ok(())
+    }
+
+    /// Print typos on one line each, in the `short` output format.
+    pub fn short(&mut self) {
+        self.short = true;
+    }
+
+    /// Only fail the check for typos of these categories.
+    pub fn error_on(&mut self, categories: &[TypoCategory]) {
+        self.error_on = categories.to_vec();
+    }
+
+    fn display_path(&self, file: &Path) -> String {
+        let relative = self.relative_to.as_ref().and_then(|base| {
+            let file = normalize(&std::path::absolute(file).ok()?);
+            file.relative_to(base).ok()
+        });
+        match relative {
+            Some(relative) => relative.to_string(),
+            None => file.display().to_string(),
+        }
+    }
+
+    pub fn report(&mut self, result: &CheckFileResult) -> anyhow::Result<()> {
+        self.counter += 1;
+        let file = self.display_path(&result.file);
+        let (counter, total_files) = (self.counter, self.total_files);
+        if let Some(ref mut export) = self.export {
+            serde_json::to_writer(&mut *export, &ResultRecord::new(result))?;
+            writeln!(export)?;
+        }
+        let typos = match result.typos {
+            Ok(ref typos) => typos,
+            Err(ref e) => {
+                self.errors += 1;
+                eprintln!("[{counter}/{total_files}] Failed to check {file}: {e:#}");
+                return Ok(());
+            }
+        };
+        self.typos += typos
+            .iter()
+            .filter(|typo| self.error_on.contains(&typo.category))
+            .count();
+        if self.progress {
+            if typos.is_empty() {
+                println!("[{counter}/{total_files}] {file}: No typos found");
+            } else if typos.len() == 1 {
+                println!("[{counter}/{total_files}] {file}: Found 1 typo");
+            } else {
+                println!(
+                    "[{counter}/{total_files}] {file}: Found {} typos",
+                    typos.len()
+                );
+            }
+        }
+        if let Some(GroupBy::Word) = self.group_by {
+            for typo in typos {
+                self.occurrences
+                    .entry(typo.word.clone())
+                    .or_default()
+                    .push((file.clone(), typo.clone()));
+            }
+            return Ok(());
+        }
+        if self.short {
+            for typo in typos {
+                println!("{}", typo.to_short(&file));
+            }
+            return Ok(());
+        }
+        for typo in typos {
+            let diagnostic: miette::Report = typo.to_diagnostic(&file).into();
+            println!("{diagnostic:?}");
+        }
+        Ok(())
+    }
+
+    /// Print anything held back until every result was seen, returning
+    /// how the check went.
+    pub fn finish(&mut self) -> Outcome {
+        // Most frequent words first
+        let mut occurrences = std::mem::take(&mut self.occurrences)
+            .into_iter()
+            .collect::<Vec<_>>();
+        occurrences.sort_by(|(a_word, a), (b_word, b)| {
+            b.len().cmp(&a.len()).then_with(|| a_word.cmp(b_word))
+        });
+        for (word, mut locations) in occurrences {
+            locations.sort_by_key(|(file, typo)| (file.clone(), typo.cell, typo.line, typo.column));
+            println!("{word} ({})", locations.len());
+            for (file, typo) in locations {
+                println!("  {}", typo.location(&file));
+            }
+        }
+        if self.errors == 1 {
+            eprintln!("Failed to check 1 file");
+        } else if self.errors > 1 {
+            eprintln!("Failed to check {} files", self.errors);
+        }
+        if self.errors > 0 {
+            Outcome::Error
+        } else if self.typos > 0 {
+            Outcome::Typos
+        } else {
+            Outcome::Clean
+        }
+    }
+}
+
+/// Counts of the files `check` walked, by how they were checked or why they
+/// were skipped.
+#[derive(Default)]
+pub struct Coverage {
+    /// Checked with a grammar
+    pub parsed: usize,
+    /// Checked word by word, without a grammar
+    pub text: usize,
+    /// Walked, but not matching the glob
+    pub excluded: usize,
+    /// Binary or otherwise not UTF-8
+    pub unreadable: usize,
+    /// Failed for any other reason
+    pub failed: usize,
+}
+
+impl Coverage {
+    /// Count a result, checked with a grammar if `parsed`.
+    pub fn add(&mut self, result: &CheckFileResult, parsed: bool) {
+        match result.typos {
+            Ok(_) if parsed => self.parsed += 1,
+            Ok(_) => self.text += 1,
+            Err(ref e) if is_unreadable(e) => self.unreadable += 1,
+            Err(_) => self.failed += 1,
+        }
+    }
+
+    pub fn checked(&self) -> usize {
+        self.parsed + self.text
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.excluded + self.unreadable + self.failed
+    }
+
+    pub fn print(&self) {
+        let walked = self.checked() + self.skipped();
+        println!("Checked {} of {walked} files", self.checked());
+        for (count, reason) in [
+            (self.parsed, "with a grammar"),
+            (self.text, "as plain text"),
+        ] {
+            if count > 0 {
+                println!("  {count} {reason}");
+            }
+        }
+        if self.skipped() == 1 {
+            println!("Skipped 1 file");
+        } else {
+            println!("Skipped {} files", self.skipped());
+        }
+        for (count, reason) in [
+            (self.excluded, "not matching the glob"),
+            (self.unreadable, "binary or not UTF-8"),
+            (self.failed, "failed to check"),
+        ] {
+            if count > 0 {
+                println!("  {count} {reason}");
+            }
+        }
+    }
+}
+
+/// Whether checking failed because the file isn't valid UTF-8 text.
+fn is_unreadable(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::InvalidData)
+    })
+}
+
+/// How long each phase of `check` took, for `--time-report`.
+#[derive(Default)]
+pub struct TimeReport {
+    /// Loading the dictionaries, which runs alongside finding files
+    pub dictionaries: Duration,
+    pub finding: Duration,
+    pub checking: Duration,
+    pub total: Duration,
+    /// Files found, and excluded by the glob
+    pub found: usize,
+    pub excluded: usize,
+    /// Files that were checked, or failed to be
+    pub checked: usize,
+}
+
+impl TimeReport {
+    /// Print the durations, with the file counts of each phase if `verbose`.
+    pub fn print(&self, verbose: bool) {
+        println!("Time report:");
+        println!("  Loading dictionaries: {:.2?}", self.dictionaries);
+        if verbose {
+            println!(
+                "  Finding files:        {:.2?} ({} found, {} excluded)",
+                self.finding, self.found, self.excluded
+            );
+            println!(
+                "  Checking files:       {:.2?} ({} checked)",
+                self.checking, self.checked
+            );
+        } else {
+            println!("  Finding files:        {:.2?}", self.finding);
+            println!("  Checking files:       {:.2?}", self.checking);
+        }
+        println!("  Total:                {:.2?}", self.total);
+    }
+}
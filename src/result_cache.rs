@@ -0,0 +1,228 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::Context;
+use dashmap::{DashMap, DashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{HashMap, Typo, filesystem};
+
+/// The file in the cache that holds the results.
+const RESULTS_NAME: &str = "results.json";
+
+/// The typos of a file as it was when last checked.
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    content_hash: String,
+    context_hash: String,
+    /// The texts the typos were found in, usually just the file's content
+    sources: Vec<String>,
+    /// Typos with the index of their source
+    typos: Vec<(usize, Typo)>,
+}
+
+/// Typos of previously checked files, reused until the file or anything
+/// else its typos depend on changes. Stored in `results.json` in the cache.
+pub struct ResultCache {
+    /// Where the results are stored
+    path: PathBuf,
+    /// Hash of the dictionaries and settings of this check
+    context_hash: String,
+    /// Entries keyed by absolute path
+    entries: DashMap<String, Entry>,
+    /// Keys of the files looked up in this run
+    seen: DashSet<String>,
+    hits: AtomicUsize,
+}
+
+impl ResultCache {
+    /// Stored entries, or none if there are none or they can't be read.
+    fn read(path: &Path) -> HashMap<String, Entry> {
+        fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn write(path: &Path, entries: &HashMap<String, Entry>) -> anyhow::Result<()> {
+        filesystem::write_atomic(path, &serde_json::to_vec(entries)?)
+            .context(format!("Failed to write results cache: {}", path.display()))
+    }
+
+    /// Load the results stored in `cache_dir`.
+    pub fn load(cache_dir: &Path, context_hash: String) -> Self {
+        let path = cache_dir.join(RESULTS_NAME);
+        Self {
+            entries: Self::read(&path).into_iter().collect(),
+            path,
+            context_hash,
+            seen: DashSet::new(),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Store the results of the files looked up in this run, keeping those
+    /// other runs stored since they were loaded. Results of files that no
+    /// longer exist are dropped.
+    pub fn save(&self) -> anyhow::Result<()> {
+        filesystem::with_lock(&self.path, || {
+            let mut entries = Self::read(&self.path);
+            for key in self.seen.iter() {
+                if let Some(entry) = self.entries.get(key.as_str()) {
+                    entries.insert(key.clone(), entry.clone());
+                }
+            }
+            entries.retain(|key, _| self.seen.contains(key) || Path::new(key).exists());
+            Self::write(&self.path, &entries)
+        })
+    }
+
+    /// Remove the results of files that no longer exist from those stored in
+    /// `cache_dir`, returning how many were removed.
+    pub fn prune_at(cache_dir: &Path) -> anyhow::Result<usize> {
+        let path = cache_dir.join(RESULTS_NAME);
+        filesystem::with_lock(&path, || {
+            let mut entries = Self::read(&path);
+            let count = entries.len();
+            entries.retain(|key, _| Path::new(key).exists());
+            let removed = count - entries.len();
+            if removed > 0 {
+                Self::write(&path, &entries)?;
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Hash of a file's content.
+    pub fn hash(content: &str) -> String {
+        blake3::hash(content.as_bytes()).to_hex().to_string()
+    }
+
+    fn key(file: &Path) -> String {
+        std::path::absolute(file)
+            .unwrap_or_else(|_| file.to_path_buf())
+            .display()
+            .to_string()
+    }
+
+    /// The typos of `file` if it was checked with the same content and
+    /// context before.
+    pub fn get(&self, file: &Path, content_hash: &str) -> Option<Vec<Typo>> {
+        let key = Self::key(file);
+        self.seen.insert(key.clone());
+        let entry = self.entries.get(&key)?;
+        if entry.content_hash != content_hash || entry.context_hash != self.context_hash {
+            return None;
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        let sources = entry
+            .sources
+            .iter()
+            .map(|source| Arc::<str>::from(source.as_str()))
+            .collect::<Vec<_>>();
+        let typos = entry
+            .typos
+            .iter()
+            .map(|(index, typo)| {
+                let mut typo = typo.clone();
+                typo.source = sources.get(*index).cloned().unwrap_or_default();
+                typo
+            })
+            .collect();
+        Some(typos)
+    }
+
+    pub fn insert(&self, file: &Path, content_hash: String, typos: &[Typo]) {
+        let mut sources: Vec<Arc<str>> = Vec::new();
+        let typos = typos
+            .iter()
+            .map(|typo| {
+                let index = match sources
+                    .iter()
+                    .position(|source| Arc::ptr_eq(source, &typo.source))
+                {
+                    Some(index) => index,
+                    None => {
+                        sources.push(typo.source.clone());
+                        sources.len() - 1
+                    }
+                };
+                (index, typo.clone())
+            })
+            .collect();
+        let entry = Entry {
+            content_hash,
+            context_hash: self.context_hash.clone(),
+            sources: sources.iter().map(ToString::to_string).collect(),
+            typos,
+        };
+        let key = Self::key(file);
+        self.seen.insert(key.clone());
+        self.entries.insert(key, entry);
+    }
+
+    /// The number of files whose results were reused.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dictionary, MultiTrie, check_text};
+
+    #[test]
+    fn test_result_cache() {
+        let dictionary = Dictionary::new_from_strings(&["hello".to_string()]);
+        let mut words = MultiTrie::new();
+        words.inner.push(Arc::new(dictionary.compile_in(None).unwrap()));
+        let source = "hello wrold";
+        let typos = check_text(source, None, &words);
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResultCache::load(dir.path(), "context".to_string());
+        let file = Path::new("does-not-exist.txt");
+        cache.insert(file, ResultCache::hash(source), &typos);
+        let cached = cache.get(file, &ResultCache::hash(source)).unwrap();
+        assert_eq!(cached[0].word, "wrold");
+        assert_eq!(&*cached[0].source, source);
+        assert!(cache.get(file, &ResultCache::hash("hello world")).is_none());
+        assert_eq!(cache.hits(), 1);
+    }
+
+    #[test]
+    fn test_save_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&a, "").unwrap();
+        let hash = ResultCache::hash("");
+        // Runs saving at the same time keep each other's results
+        let first = ResultCache::load(dir.path(), "context".to_string());
+        let second = ResultCache::load(dir.path(), "context".to_string());
+        first.insert(&a, hash.clone(), &[]);
+        second.insert(&b, hash.clone(), &[]);
+        first.save().unwrap();
+        second.save().unwrap();
+        let cache = ResultCache::load(dir.path(), "context".to_string());
+        assert!(cache.get(&a, &hash).is_some());
+        assert!(cache.get(&b, &hash).is_some());
+
+        // `b.txt` was never written, so it's dropped once it isn't checked
+        let cache = ResultCache::load(dir.path(), "context".to_string());
+        cache.save().unwrap();
+        let cache = ResultCache::load(dir.path(), "context".to_string());
+        assert!(cache.get(&a, &hash).is_some());
+        assert!(cache.get(&b, &hash).is_none());
+
+        fs::remove_file(&a).unwrap();
+        assert_eq!(ResultCache::prune_at(dir.path()).unwrap(), 1);
+        assert!(ResultCache::read(&dir.path().join(RESULTS_NAME)).is_empty());
+    }
+}
@@ -1,10 +1,59 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
-use anyhow::Context;
+use anyhow::{Context, bail};
 use git2::Repository;
 use serde::{Deserialize, Serialize};
 
-use crate::filesystem::git_path;
+use crate::{HashMap, filesystem::git_path};
+
+/// The glob that files found in folders must match if neither `--glob` nor
+/// `globs` is given.
+pub const DEFAULT_GLOB: &str = "**/*.*";
+
+/// Set by `--config-from-git-root`
+static CONFIG_FROM_GIT_ROOT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_config_from_git_root(enabled: bool) {
+    CONFIG_FROM_GIT_ROOT.store(enabled, Ordering::Relaxed);
+}
+
+/// Hours after which git dictionaries are fetched again if
+/// `git_update_hours` isn't set.
+pub const DEFAULT_GIT_UPDATE_HOURS: u64 = 3;
+
+/// Commits of history that git dictionaries are cloned with if
+/// `git_clone_depth` isn't set.
+pub const DEFAULT_GIT_CLONE_DEPTH: u32 = 1;
+
+/// Set by `--offline`
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_offline(enabled: bool) {
+    OFFLINE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// The settings file used when none is given: `code-spellcheck.json` in the
+/// working directory, or at the root of the enclosing git repository with
+/// `--config-from-git-root`.
+pub fn default_settings_path() -> PathBuf {
+    const FILE_NAME: &str = "code-spellcheck.json";
+    if CONFIG_FROM_GIT_ROOT.load(Ordering::Relaxed)
+        && let Ok(repo) = Repository::discover(".")
+        && let Some(root) = repo.workdir()
+    {
+        return root.join(FILE_NAME);
+    }
+    PathBuf::from(FILE_NAME)
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -20,8 +69,8 @@ impl CustomDictionaryDefinitionPath {
     }
 }
 
+/// The revision of a git dictionary to check out, like `{"tag": "v1.0"}`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(untagged)]
 pub enum CustomDictionaryDefinitionGitIdent {
     #[serde(rename = "branch")]
     Branch(String),
@@ -31,6 +80,60 @@ pub enum CustomDictionaryDefinitionGitIdent {
     Commit(String),
 }
 
+impl std::fmt::Display for CustomDictionaryDefinitionGitIdent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Branch(branch) => write!(f, "branch {branch}"),
+            Self::Tag(tag) => write!(f, "tag {tag}"),
+            Self::Commit(commit) => write!(f, "commit {commit}"),
+        }
+    }
+}
+
+impl CustomDictionaryDefinitionGitIdent {
+    /// The refspec that fetches the identity, and the revision it's then
+    /// found at.
+    fn refspec(&self) -> (String, String) {
+        match self {
+            Self::Branch(branch) => (
+                format!("+refs/heads/{branch}:refs/remotes/origin/{branch}"),
+                format!("refs/remotes/origin/{branch}"),
+            ),
+            Self::Tag(tag) => (
+                format!("+refs/tags/{tag}:refs/tags/{tag}"),
+                format!("refs/tags/{tag}"),
+            ),
+            Self::Commit(commit) => (commit.clone(), commit.clone()),
+        }
+    }
+
+    /// Check out the identity in `repo`, discarding local changes. Branches
+    /// are fetched first with `update`, and tags and commits only if they're
+    /// missing, since they don't move. Nothing is fetched when `offline`.
+    pub fn checkout(&self, repo: &Repository, update: bool, offline: bool) -> anyhow::Result<()> {
+        let (refspec, revision) = self.refspec();
+        let find = || {
+            repo.revparse_single(&revision)
+                .and_then(|object| object.peel_to_commit())
+        };
+        let moves = matches!(self, Self::Branch(_));
+        let commit = match find() {
+            Ok(commit) if offline || !(update && moves) => commit,
+            _ => {
+                if offline {
+                    bail!("Can't fetch {self} while offline");
+                }
+                let mut remote = repo.find_remote("origin")?;
+                crate::git::fetch(repo, &[&refspec], &mut remote)
+                    .context(format!("Failed to fetch {self}"))?;
+                find().context(format!("{self} not found"))?
+            }
+        };
+        crate::git::checkout_detached(repo, commit.id())
+            .context(format!("Failed to check out {self}"))
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum CustomDictionaryDefinitionGit {
@@ -41,32 +144,52 @@ pub enum CustomDictionaryDefinitionGit {
     },
 }
 
+/// How git dictionaries are cloned and updated, see [`Settings::git_options`].
+#[derive(Clone, Copy, Debug)]
+pub struct GitOptions {
+    /// How long after which clones are fetched again, or `None` to never
+    /// fetch them
+    pub update_after: Option<Duration>,
+    /// Commits of history to clone, or 0 for all of them
+    pub depth: u32,
+}
+
 impl CustomDictionaryDefinitionGit {
-    pub fn init(&self) -> anyhow::Result<()> {
+    /// Clone the repository if it's missing, or fetch it if it wasn't
+    /// accessed within `update_after`, then check out its identity. `None`
+    /// never fetches an existing clone.
+    pub fn init(&self, options: GitOptions) -> anyhow::Result<()> {
+        let GitOptions {
+            update_after,
+            depth,
+        } = options;
         let url = self.url();
         let repo_path = self.path();
-        let _repo = if !repo_path.exists() {
+        let should_update = match update_after {
+            Some(interval) if repo_path.exists() => {
+                let repo_path_info = fs::metadata(&repo_path)?;
+                repo_path_info.accessed()?.elapsed()? > interval
+            }
+            _ => false,
+        };
+        let repo = if !repo_path.exists() {
+            if update_after.is_none() {
+                bail!("Can't clone {url} while offline");
+            }
             fs::create_dir_all(&repo_path).context(format!(
                 "Failed to create temporary directory: {}",
                 repo_path.display()
             ))?;
 
             println!("Cloning {url}");
-            crate::git::clone(&url, &repo_path)
+            crate::git::clone(&url, &repo_path, depth)
                 .with_context(|| format!("failed to clone: {url}"))?
         } else {
             let res = Repository::open(&repo_path);
             match res {
                 Ok(repo) => {
-                    const SECONDS_IN_HOUR: u64 = 60 * 60;
-
-                    // TODO: choose when to update repo
-                    let repo_path_info = fs::metadata(&repo_path)?;
-                    let secs_since_last_accessed = repo_path_info.accessed()?.elapsed()?.as_secs();
-
-                    let should_update = secs_since_last_accessed > SECONDS_IN_HOUR * 3;
-
-                    if should_update {
+                    // A pinned identity is fetched by `checkout`
+                    if should_update && matches!(self, Self::Simple(_)) {
                         let mut remote = repo.find_remote("origin")?;
                         let remote_branch = "main";
                         let fetch_commit = crate::git::fetch(&repo, &[remote_branch], &mut remote)?;
@@ -77,15 +200,20 @@ impl CustomDictionaryDefinitionGit {
                 }
                 Err(e) => {
                     eprintln!("Failed to open temporary directory: {e}");
+                    if update_after.is_none() {
+                        bail!("Can't reclone {url} while offline");
+                    }
                     // Reclone
                     fs::remove_dir_all(&repo_path).ok();
                     println!("Recloning {url}");
-                    crate::git::clone(&url, &repo_path)
+                    crate::git::clone(&url, &repo_path, depth)
                         .with_context(|| format!("failed to clone: {url}"))?
                 }
             }
         };
-        // TODO: ensure the repo is in a clean state and on the correct identifier
+        if let Self::Custom { identity, .. } = self {
+            identity.checkout(&repo, should_update, update_after.is_none())?;
+        }
         Ok(())
     }
 
@@ -129,6 +257,9 @@ pub struct CustomDictionaryDefinition {
     pub typ: CustomDictionaryDefinitionType,
     #[serde(default)]
     pub globs: Vec<String>,
+    /// Always recompile the dictionary instead of caching it
+    #[serde(default, alias = "noCache")]
+    pub no_cache: bool,
 }
 
 impl CustomDictionaryDefinition {
@@ -162,10 +293,87 @@ pub struct Settings {
     pub dictionaries: Vec<DictionaryName>,
     #[serde(default, alias = "dictionaryDefinitions")]
     pub dictionary_definitions: Vec<CustomDictionaryDefinition>,
+    /// A folder of dictionaries vendored in the project, laid out like the
+    /// store and used in addition to it
+    #[serde(default, alias = "dictionaryDir")]
+    pub dictionary_dir: Option<PathBuf>,
     #[serde(default, alias = "ignorePaths")]
     pub ignore_paths: Vec<String>,
     #[serde(default)]
     pub words: Vec<String>,
+    /// Maps file extensions to language identifiers, taking precedence over
+    /// the built-in extension table (e.g. `"rsx": "rust"`)
+    #[serde(default, alias = "languageMap")]
+    pub language_map: HashMap<String, String>,
+    /// Prefixes of generated identifiers to strip before checking (e.g.
+    /// `"pb_"`)
+    #[serde(default, alias = "stripPrefixes")]
+    pub strip_prefixes: Vec<String>,
+    /// Regexes for tokens that are never reported (e.g. `"^v\\d+$"`)
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Dictionaries of the target language, used to check the translations
+    /// (`msgstr`) of gettext catalogs. Translations aren't checked if empty
+    #[serde(default, alias = "translationDictionaries")]
+    pub translation_dictionaries: Vec<String>,
+    /// Dictionaries enabled only for files of a language, keyed by language
+    /// identifier (e.g. `"rust": ["rust"]`). Missing dictionaries are skipped
+    #[serde(default, alias = "languageSettings")]
+    pub language_settings: HashMap<String, Vec<String>>,
+    /// Similarity from 0 to 1 that a suggestion must exceed, overridden by
+    /// `--suggestion-threshold`
+    #[serde(default, alias = "suggestionThreshold")]
+    pub suggestion_threshold: Option<f64>,
+    /// Node kinds checked by `--comments-only` and `--strings-only`, keyed by
+    /// language identifier, in addition to the built-in ones
+    #[serde(default, alias = "proseNodeKinds")]
+    pub prose_node_kinds: HashMap<String, ProseNodeKinds>,
+    /// Node kinds that are never checked, keyed by language identifier, in
+    /// addition to the built-in ones (e.g. `"rust": ["attribute_item"]`)
+    #[serde(default, alias = "skipNodeKinds")]
+    pub skip_node_kinds: HashMap<String, Vec<String>>,
+    /// Globs that files found in folders must match one of, overridden by
+    /// `--glob` (e.g. `["**/*.*", "**/Dockerfile"]`). [`DEFAULT_GLOB`] if
+    /// empty
+    #[serde(default)]
+    pub globs: Vec<String>,
+    /// Never fetch git dictionaries, using existing clones as they are. Also
+    /// set by `--offline`
+    #[serde(default)]
+    pub offline: bool,
+    /// Hours after which git dictionaries are fetched again
+    /// [default: [`DEFAULT_GIT_UPDATE_HOURS`]]
+    #[serde(default, alias = "gitUpdateHours")]
+    pub git_update_hours: Option<u64>,
+    /// Commits of history git dictionaries are cloned with, or 0 for all of
+    /// them [default: [`DEFAULT_GIT_CLONE_DEPTH`]]
+    #[serde(default, alias = "gitCloneDepth")]
+    pub git_clone_depth: Option<u32>,
+    /// Words and dictionaries added for the files matching globs, on top of
+    /// the others
+    #[serde(default)]
+    pub overrides: Vec<Override>,
+}
+
+/// Extra words and dictionaries for the files matching some globs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Override {
+    /// Globs of the files the override applies to, like `"legacy/**"`
+    #[serde(default)]
+    pub globs: Vec<String>,
+    #[serde(default)]
+    pub words: Vec<String>,
+    #[serde(default)]
+    pub dictionaries: Vec<String>,
+}
+
+/// Kinds of the tree-sitter nodes that hold a language's prose.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProseNodeKinds {
+    #[serde(default)]
+    pub comments: Vec<String>,
+    #[serde(default)]
+    pub strings: Vec<String>,
 }
 
 impl Default for Settings {
@@ -179,8 +387,36 @@ impl Default for Settings {
                 DictionaryName::Simple("words".to_string()),
             ],
             dictionary_definitions: vec![],
+            dictionary_dir: None,
             ignore_paths: vec![],
             words: vec![],
+            language_map: HashMap::default(),
+            strip_prefixes: vec![],
+            patterns: vec![],
+            translation_dictionaries: vec![],
+            language_settings: [
+                ("c", "cpp"),
+                ("cpp", "cpp"),
+                ("go", "golang"),
+                ("html", "html"),
+                ("javascript", "typescript"),
+                ("python", "python"),
+                ("ruby", "ruby"),
+                ("rust", "rust"),
+                ("tsx", "typescript"),
+                ("typescript", "typescript"),
+            ]
+            .into_iter()
+            .map(|(language, dictionary)| (language.to_string(), vec![dictionary.to_string()]))
+            .collect(),
+            suggestion_threshold: None,
+            prose_node_kinds: HashMap::default(),
+            skip_node_kinds: HashMap::default(),
+            globs: vec![],
+            offline: false,
+            git_update_hours: None,
+            git_clone_depth: None,
+            overrides: vec![],
         }
     }
 }
@@ -191,12 +427,49 @@ impl Settings {
         Self::default()
     }
 
+    /// How long after which git dictionaries are fetched again, or `None` if
+    /// they're never fetched because of `offline` or `--offline`.
+    pub fn git_update_interval(&self) -> Option<Duration> {
+        if self.offline || offline() {
+            return None;
+        }
+        let hours = self.git_update_hours.unwrap_or(DEFAULT_GIT_UPDATE_HOURS);
+        Some(Duration::from_secs(hours * 60 * 60))
+    }
+
+    /// How git dictionaries are cloned and updated.
+    pub fn git_options(&self) -> GitOptions {
+        GitOptions {
+            update_after: self.git_update_interval(),
+            depth: self.git_clone_depth.unwrap_or(DEFAULT_GIT_CLONE_DEPTH),
+        }
+    }
+
     pub fn load_from_file<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Self> {
         let data = fs::read_to_string(path)?;
         let settings: Self = serde_hjson::from_str(&data)?;
+        if let Some(threshold) = settings.suggestion_threshold
+            && !(0.0..=1.0).contains(&threshold)
+        {
+            bail!("suggestion_threshold must be between 0 and 1, found {threshold}");
+        }
         Ok(settings)
     }
 
+    /// The globs that files found in folders must match one of: `glob` if
+    /// given, else `globs`, else [`DEFAULT_GLOB`].
+    pub fn glob_patterns(&self, glob: Option<&str>) -> anyhow::Result<Vec<glob::Pattern>> {
+        let globs = match glob {
+            Some(glob) => vec![glob],
+            None if !self.globs.is_empty() => self.globs.iter().map(String::as_str).collect(),
+            None => vec![DEFAULT_GLOB],
+        };
+        globs
+            .into_iter()
+            .map(|glob| glob::Pattern::new(glob).context(format!("Invalid glob: {glob}")))
+            .collect()
+    }
+
     pub fn save_to_file<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<()> {
         let data = serde_json::to_string_pretty(self)?;
         std::fs::write(path, data)?;
@@ -204,7 +477,7 @@ impl Settings {
     }
 
     pub fn load(override_: Option<String>) -> Self {
-        let path = override_.unwrap_or_else(|| "code-spellcheck.json".to_string());
+        let path = override_.unwrap_or_else(|| default_settings_path().display().to_string());
         if std::path::Path::new(&path).exists() {
             Self::load_from_file(&path).unwrap_or_else(|e| {
                 eprintln!("Error loading settings from {path}: {e}");
@@ -215,3 +488,171 @@ impl Settings {
         }
     }
 }
+
+/// Keys of a settings file, including aliases.
+const SETTINGS_KEYS: &[&str] = &[
+    "dictionaries",
+    "dictionary_definitions",
+    "dictionaryDefinitions",
+    "dictionary_dir",
+    "dictionaryDir",
+    "ignore_paths",
+    "ignorePaths",
+    "words",
+    "language_map",
+    "languageMap",
+    "strip_prefixes",
+    "stripPrefixes",
+    "patterns",
+    "translation_dictionaries",
+    "translationDictionaries",
+    "language_settings",
+    "languageSettings",
+    "suggestion_threshold",
+    "suggestionThreshold",
+    "prose_node_kinds",
+    "proseNodeKinds",
+    "skip_node_kinds",
+    "skipNodeKinds",
+    "globs",
+    "offline",
+    "git_update_hours",
+    "gitUpdateHours",
+    "git_clone_depth",
+    "gitCloneDepth",
+    "overrides",
+];
+
+/// Keys of an entry of `dictionary_definitions`, including aliases.
+const DEFINITION_KEYS: &[&str] = &[
+    "name", "aliases", "path", "git", "globs", "no_cache", "noCache",
+];
+
+/// Keys of an entry of `overrides`.
+const OVERRIDE_KEYS: &[&str] = &["globs", "words", "dictionaries"];
+
+/// Keys of a settings file that aren't settings, as paths like
+/// `dictionary_definitions[0].pth`. These are ignored when loading.
+pub fn unknown_keys(value: &serde_json::Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let Some(object) = value.as_object() else {
+        return unknown;
+    };
+    for (key, value) in object {
+        if !SETTINGS_KEYS.contains(&key.as_str()) {
+            unknown.push(key.clone());
+            continue;
+        }
+        let known = match key.as_str() {
+            "dictionary_definitions" | "dictionaryDefinitions" => DEFINITION_KEYS,
+            "overrides" => OVERRIDE_KEYS,
+            _ => continue,
+        };
+        let definitions = value.as_array().map(Vec::as_slice).unwrap_or_default();
+        for (index, definition) in definitions.iter().enumerate() {
+            let keys = definition.as_object().into_iter().flat_map(|d| d.keys());
+            for definition_key in keys {
+                if !known.contains(&definition_key.as_str()) {
+                    unknown.push(format!("{key}[{index}].{definition_key}"));
+                }
+            }
+        }
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_keys() {
+        let value = serde_json::json!({
+            "words": ["foo"],
+            "ignorePaths": ["target"],
+            "dictionnaries": ["en-US"],
+            "dictionary_definitions": [
+                {"name": "a", "path": "a.txt", "noCache": true},
+                {"name": "b", "pth": "b.txt"}
+            ],
+            "overrides": [{"globs": ["legacy/**"], "word": ["foo"]}]
+        });
+        assert_eq!(
+            unknown_keys(&value),
+            vec![
+                "dictionary_definitions[1].pth",
+                "dictionnaries",
+                "overrides[0].word"
+            ]
+        );
+        // Every setting is known
+        let defaults = serde_json::to_value(Settings::default()).unwrap();
+        assert!(unknown_keys(&defaults).is_empty());
+    }
+
+    #[test]
+    fn test_glob_patterns() {
+        let matches = |settings: &Settings, glob: Option<&str>, path: &str| {
+            let patterns = settings.glob_patterns(glob).unwrap();
+            patterns.iter().any(|pattern| pattern.matches(path))
+        };
+        let mut settings = Settings::default();
+        assert!(matches(&settings, None, "src/main.rs"));
+        assert!(!matches(&settings, None, "docker/Dockerfile"));
+        settings.globs = vec!["**/*.*".to_string(), "**/Dockerfile".to_string()];
+        assert!(matches(&settings, None, "src/main.rs"));
+        assert!(matches(&settings, None, "docker/Dockerfile"));
+        assert!(matches(&settings, Some("**/*"), "LICENSE"));
+        assert!(!matches(&settings, Some("**/*.rs"), "docker/Dockerfile"));
+    }
+
+    #[test]
+    fn test_checkout_identity() {
+        let dir = std::env::temp_dir().join(format!("csc-identity-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let upstream = Repository::init(dir.join("upstream")).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let commit = |words: &str, parents: &[&git2::Commit]| {
+            fs::write(dir.join("upstream/words.txt"), words).unwrap();
+            let mut index = upstream.index().unwrap();
+            index.add_path(std::path::Path::new("words.txt")).unwrap();
+            let tree = upstream.find_tree(index.write_tree().unwrap()).unwrap();
+            let id = upstream
+                .commit(Some("HEAD"), &signature, &signature, words, &tree, parents)
+                .unwrap();
+            upstream.find_commit(id).unwrap()
+        };
+        let first = commit("first", &[]);
+        let second = commit("second", &[&first]);
+        upstream
+            .tag_lightweight("v1", first.as_object(), false)
+            .unwrap();
+
+        let clone =
+            Repository::clone(dir.join("upstream").to_str().unwrap(), dir.join("clone")).unwrap();
+        let words = || fs::read_to_string(dir.join("clone/words.txt")).unwrap();
+        let tag = CustomDictionaryDefinitionGitIdent::Tag("v1".to_string());
+        tag.checkout(&clone, false, false).unwrap();
+        assert_eq!(words(), "first");
+        let commit = CustomDictionaryDefinitionGitIdent::Commit(second.id().to_string());
+        commit.checkout(&clone, false, true).unwrap();
+        assert_eq!(words(), "second");
+        let missing = CustomDictionaryDefinitionGitIdent::Tag("v2".to_string());
+        assert!(missing.checkout(&clone, false, true).is_err());
+        assert!(missing.checkout(&clone, false, false).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_git_update_interval() {
+        let mut settings = Settings::default();
+        assert_eq!(
+            settings.git_update_interval(),
+            Some(Duration::from_secs(3 * 60 * 60))
+        );
+        settings.git_update_hours = Some(0);
+        assert_eq!(settings.git_update_interval(), Some(Duration::ZERO));
+        settings.offline = true;
+        assert_eq!(settings.git_update_interval(), None);
+    }
+}
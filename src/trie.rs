@@ -1,7 +1,7 @@
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug};
 
 use bincode::{Decode, Encode};
-use fst::{IntoStreamer, automaton::Levenshtein};
+use fst::{IntoStreamer, Streamer, automaton::Levenshtein};
 
 use crate::dictionary::{Command, Rule};
 
@@ -11,6 +11,24 @@ struct TrieRepr {
     options: TrieOptions,
 }
 
+/// Value of an allowed word in the fst.
+const ALLOW: u64 = 0;
+/// Value of a disallowed word in the fst.
+const DISALLOW: u64 = 1;
+/// Allowed words with a known frequency are stored as the frequency plus
+/// this offset.
+const FREQUENCY_OFFSET: u64 = 2;
+
+/// Combine the values of a word in two rule sets: disallowing wins, then a
+/// known frequency.
+fn merge_values(a: u64, b: u64) -> u64 {
+    if a == DISALLOW || b == DISALLOW {
+        DISALLOW
+    } else {
+        a.max(b)
+    }
+}
+
 #[derive(Clone)]
 pub struct Trie {
     pub root: fst::map::Map<Vec<u8>>,
@@ -65,9 +83,33 @@ impl Trie {
         })
     }
 
+    /// Combine several tries into one, so lookups need a single search. A word
+    /// disallowed by any of the tries stays disallowed.
+    pub fn union<'a, I>(tries: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = &'a Trie>,
+    {
+        let mut options = TrieOptions::default();
+        let mut op = fst::map::OpBuilder::new();
+        for trie in tries {
+            options.case_sensitive |= trie.options.case_sensitive;
+            op = op.add(&trie.root);
+        }
+        let mut builder = fst::MapBuilder::memory();
+        let mut stream = op.union();
+        while let Some((word, values)) = stream.next() {
+            let value = values.iter().map(|v| v.value).fold(ALLOW, merge_values);
+            builder.insert(word, value)?;
+        }
+        Ok(Self {
+            root: fst::map::Map::new(builder.into_inner()?)?,
+            options,
+        })
+    }
+
     pub fn dump_to_file<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<()> {
         let data = self.dump()?;
-        std::fs::write(path, data)?;
+        crate::filesystem::write_atomic(path, &data)?;
         Ok(())
     }
 
@@ -76,16 +118,54 @@ impl Trie {
         Self::load(&data)
     }
 
+    /// Hash of the words and their rules, which changes with any of them.
+    #[must_use]
+    pub fn content_hash(&self) -> String {
+        blake3::hash(self.root.as_fst().as_bytes())
+            .to_hex()
+            .to_string()
+    }
+
     #[must_use]
     pub fn contains(&self, word: &str) -> bool {
         self.root.contains_key(word)
     }
 
+    /// Whether the word is forbidden, with a leading `!`.
+    #[must_use]
+    pub fn is_disallowed(&self, word: &str) -> bool {
+        self.root.get(word) == Some(DISALLOW)
+    }
+
+    /// How frequently an allowed word is used, if the dictionary says.
+    #[must_use]
+    pub fn frequency(&self, word: &str) -> Option<u64> {
+        self.root
+            .get(word)
+            .and_then(|value| value.checked_sub(FREQUENCY_OFFSET))
+    }
+
     #[must_use]
     pub fn to_vec(&self) -> Vec<String> {
         self.root.stream().into_str_keys().unwrap()
     }
 
+    /// Decompile the trie back into allow and disallow rules, sorted by word.
+    #[must_use]
+    pub fn to_rules(&self) -> Vec<Rule> {
+        self.root
+            .stream()
+            .into_str_vec()
+            .unwrap()
+            .into_iter()
+            .map(|(word, value)| match value {
+                ALLOW => Rule::Allow(word),
+                DISALLOW => Rule::Disallow(word),
+                _ => Rule::AllowWithFrequency(word, value - FREQUENCY_OFFSET),
+            })
+            .collect()
+    }
+
     pub fn check(&self, word: &str) -> anyhow::Result<Option<String>> {
         let lev = Levenshtein::new(word, 1)?;
         let stream = self.root.search(lev).into_stream();
@@ -123,33 +203,134 @@ impl TrieOptions {
         match command {
             Command::CaseSensitive => self.case_sensitive = true,
             Command::Cache(cache) => self.cache = *cache,
+            // Handled when the dictionary is compiled
+            Command::Affixes => {}
         }
     }
 }
 
 impl From<&[Rule]> for Trie {
     fn from(rules: &[Rule]) -> Self {
-        let mut trie = Vec::new();
+        // Sorted, unique keys as fst requires, with disallow winning over
+        // allow
+        let mut trie = BTreeMap::new();
         let mut options = TrieOptions::default();
         for rule in rules {
-            match rule {
-                Rule::Allow(word) => {
-                    trie.push((word, 0));
-                }
-                Rule::Disallow(word) => {
-                    trie.push((word, 1));
+            let (word, value) = match rule {
+                Rule::Allow(word) | Rule::AllowWithAffixes(word, _) => (word, ALLOW),
+                Rule::AllowWithFrequency(word, frequency) => {
+                    (word, frequency.saturating_add(FREQUENCY_OFFSET))
                 }
+                Rule::Disallow(word) => (word, DISALLOW),
                 Rule::Command(command) => {
                     options.add_command(command);
+                    continue;
                 }
-                Rule::Comment(_) => {}
-            }
+                Rule::Comment(_) => continue,
+            };
+            trie.entry(word.as_str())
+                .and_modify(|existing: &mut u64| *existing = merge_values(*existing, value))
+                .or_insert(value);
         }
-        trie.sort_by_key(|(word, _)| word.to_string());
-        trie.dedup();
         Self {
             root: fst::map::Map::from_iter(trie).unwrap(),
             options,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflicting_rules() {
+        let rules = [
+            Rule::Allow("word".to_string()),
+            Rule::Disallow("word".to_string()),
+            Rule::Allow("word".to_string()),
+            Rule::Allow("other".to_string()),
+        ];
+        let trie = Trie::from(rules.as_slice());
+        let rules = trie
+            .to_rules()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(rules, ["other", "!word"]);
+    }
+
+    #[test]
+    fn test_union() {
+        let a = Trie::from([Rule::Allow("a".to_string()), Rule::Allow("b".to_string())].as_slice());
+        let b = Trie::from(
+            [
+                Rule::Disallow("b".to_string()),
+                Rule::Allow("c".to_string()),
+            ]
+            .as_slice(),
+        );
+        let trie = Trie::union([&a, &b]).unwrap();
+        let rules = trie
+            .to_rules()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(rules, ["a", "!b", "c"]);
+    }
+
+    #[test]
+    fn test_frequency() {
+        let a = Trie::from(
+            [
+                Rule::AllowWithFrequency("common".to_string(), 1000),
+                Rule::AllowWithFrequency("rare".to_string(), 0),
+                Rule::Allow("plain".to_string()),
+                Rule::AllowWithFrequency("banned".to_string(), 5),
+            ]
+            .as_slice(),
+        );
+        assert_eq!(a.frequency("common"), Some(1000));
+        assert_eq!(a.frequency("rare"), Some(0));
+        assert_eq!(a.frequency("plain"), None);
+        let b = Trie::from(
+            [
+                Rule::Disallow("banned".to_string()),
+                Rule::Allow("common".to_string()),
+            ]
+            .as_slice(),
+        );
+        let trie = Trie::union([&a, &b]).unwrap();
+        let rules = trie
+            .to_rules()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(rules, ["!banned", "common\t1000", "plain", "rare\t0"]);
+    }
+
+    #[test]
+    fn test_unsorted_duplicate_rules() {
+        let rules = ["b", "a", "c", "a", "b"]
+            .iter()
+            .map(|word| Rule::Allow(word.to_string()))
+            .collect::<Vec<_>>();
+        let trie = Trie::from(rules.as_slice());
+        assert_eq!(trie.to_vec(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_disallow_before_allow() {
+        let rules = [
+            Rule::Disallow("word".to_string()),
+            Rule::Allow("word".to_string()),
+        ];
+        let trie = Trie::from(rules.as_slice());
+        let rules = trie
+            .to_rules()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(rules, ["!word"]);
+    }
+}
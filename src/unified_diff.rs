@@ -0,0 +1,108 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Extract the added lines of each file in a unified diff.
+///
+/// Each file's lines are placed at their line numbers in the new file, with
+/// every other line left empty, so positions in the result match the
+/// original file. Deleted files are skipped.
+pub fn added_lines(diff: &str) -> BTreeMap<PathBuf, String> {
+    let mut files: BTreeMap<PathBuf, Vec<&str>> = BTreeMap::new();
+    let mut current = None;
+    let (mut old_remaining, mut new_remaining, mut line) = (0usize, 0usize, 0usize);
+    for text in diff.lines() {
+        if old_remaining > 0 || new_remaining > 0 {
+            match text.chars().next() {
+                Some('+') => {
+                    if line > 0
+                        && let Some(lines) = current.as_ref().and_then(|file| files.get_mut(file))
+                    {
+                        if lines.len() < line {
+                            lines.resize(line, "");
+                        }
+                        lines[line - 1] = &text[1..];
+                    }
+                    line += 1;
+                    new_remaining = new_remaining.saturating_sub(1);
+                }
+                Some('-') => old_remaining = old_remaining.saturating_sub(1),
+                Some('\\') => {}
+                _ => {
+                    line += 1;
+                    old_remaining = old_remaining.saturating_sub(1);
+                    new_remaining = new_remaining.saturating_sub(1);
+                }
+            }
+        } else if let Some(path) = text.strip_prefix("+++ ") {
+            // Drop a trailing timestamp and the `b/` prefix git adds
+            let path = path.split('\t').next().unwrap_or(path).trim_end();
+            current = (path != "/dev/null")
+                .then(|| PathBuf::from(path.strip_prefix("b/").unwrap_or(path)));
+            if let Some(ref file) = current {
+                files.entry(file.clone()).or_default();
+            }
+        } else if let Some(header) = text.strip_prefix("@@ ") {
+            let mut ranges = header.split_whitespace();
+            let old = ranges.next().and_then(|range| range.strip_prefix('-'));
+            let new = ranges.next().and_then(|range| range.strip_prefix('+'));
+            let (Some(old), Some(new)) = (old.map(parse_range), new.map(parse_range)) else {
+                continue;
+            };
+            old_remaining = old.1;
+            (line, new_remaining) = new;
+        }
+    }
+    files
+        .into_iter()
+        .map(|(file, lines)| (file, lines.join("\n")))
+        .collect()
+}
+
+/// Parse a hunk range like `12,3` or `12` into its start and length.
+fn parse_range(range: &str) -> (usize, usize) {
+    let (start, length) = range.split_once(',').unwrap_or((range, "1"));
+    (start.parse().unwrap_or(0), length.parse().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_added_lines() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@
+ fn main() {
+-    let x = 1;
++    let helo = 1;
++    // wrold
+ }
+@@ -10 +11 @@
+-old
++new
+";
+        let files = added_lines(diff);
+        let source = &files[&PathBuf::from("src/lib.rs")];
+        let lines = source.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "");
+        assert_eq!(lines[1], "    let helo = 1;");
+        assert_eq!(lines[2], "    // wrold");
+        assert_eq!(lines[3], "");
+        assert_eq!(lines[10], "new");
+    }
+
+    #[test]
+    fn test_deleted_file() {
+        let diff = "\
+--- a/removed.txt
++++ /dev/null
+@@ -1,2 +0,0 @@
+-one
+-two
+";
+        assert!(added_lines(diff).is_empty());
+    }
+}